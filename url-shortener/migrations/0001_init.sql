@@ -0,0 +1,50 @@
+CREATE TABLE IF NOT EXISTS urls (
+                                    id BIGSERIAL PRIMARY KEY,
+                                    short_code VARCHAR(12) NOT NULL UNIQUE,
+    original_url TEXT UNIQUE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    expires_at TIMESTAMP WITH TIME ZONE NULL,
+                             is_deleted BOOLEAN DEFAULT FALSE,
+                             clicks BIGINT DEFAULT 0
+                             );
+
+CREATE TABLE IF NOT EXISTS clicks (
+                                      id BIGSERIAL PRIMARY KEY,
+                                      url_id BIGINT NOT NULL REFERENCES urls(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    ip INET NULL,
+    user_agent TEXT NULL,
+    referer TEXT NULL
+    );
+
+CREATE INDEX IF NOT EXISTS idx_clicks_url_id_created_at ON clicks (url_id, created_at);
+
+-- an expired or deleted link must not block its URL from being shortened again
+ALTER TABLE urls DROP CONSTRAINT IF EXISTS urls_original_url_key;
+CREATE INDEX IF NOT EXISTS idx_urls_original_url ON urls (original_url);
+CREATE INDEX IF NOT EXISTS idx_urls_expires_at ON urls (expires_at) WHERE expires_at IS NOT NULL;
+
+-- backs the "sequence" code strategy; starts at 62^3 so codes are at least four characters
+CREATE SEQUENCE IF NOT EXISTS short_code_seq START WITH 238328;
+
+CREATE TABLE IF NOT EXISTS owners (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    owner_id BIGINT NOT NULL REFERENCES owners(id),
+    -- sha256 hex of the bearer token, the token itself is never stored
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+    revoked_at TIMESTAMP WITH TIME ZONE NULL
+);
+
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS owner_id BIGINT NULL REFERENCES owners(id);
+CREATE INDEX IF NOT EXISTS idx_urls_owner_id ON urls (owner_id);
+
+-- 302 by default so browsers keep asking us, which keeps clicks counted and target edits visible
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS redirect_status SMALLINT NOT NULL DEFAULT 302
+    CHECK (redirect_status IN (301, 302, 307, 308));
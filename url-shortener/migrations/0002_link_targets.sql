@@ -0,0 +1,19 @@
+-- alternative destinations for one short code; urls.original_url stays the fallback when no target matches
+CREATE TABLE IF NOT EXISTS link_targets (
+    id BIGSERIAL PRIMARY KEY,
+    url_id BIGINT NOT NULL REFERENCES urls(id),
+    target_url TEXT NOT NULL,
+    weight INTEGER NOT NULL DEFAULT 1 CHECK (weight >= 0),
+    -- ISO 3166-1 alpha-2, matched against the country header
+    country TEXT NULL CHECK (country ~ '^[A-Z]{2}$'),
+    device TEXT NULL CHECK (device IN ('mobile', 'tablet', 'desktop')),
+    -- replaced targets are kept so clicks recorded against them still resolve
+    is_deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_targets_url_id ON link_targets (url_id) WHERE NOT is_deleted;
+
+ALTER TABLE clicks ADD COLUMN IF NOT EXISTS target_id BIGINT NULL REFERENCES link_targets(id);
+
+CREATE INDEX IF NOT EXISTS idx_clicks_target_id ON clicks (target_id) WHERE target_id IS NOT NULL;
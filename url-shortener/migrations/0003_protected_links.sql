@@ -0,0 +1,6 @@
+-- argon2 phc string; links with a password show a form instead of redirecting
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS password_hash TEXT NULL;
+-- the link behaves as expired once uses reaches max_clicks; null means unlimited
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS max_clicks BIGINT NULL CHECK (max_clicks > 0);
+-- counted synchronously on every redirect of a limited link, unlike the batched clicks column
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS uses BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- GET /api/links walks an owner's links newest first, keyed on (created_at, id);
+-- this also serves the plain owner_id lookups the old index was for
+CREATE INDEX IF NOT EXISTS idx_urls_owner_created ON urls (owner_id, created_at DESC, id DESC);
+DROP INDEX IF EXISTS idx_urls_owner_id;
+
+-- substring search on the destination, ILIKE '%...%' can use trigrams
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS idx_urls_original_url_trgm ON urls USING gin (original_url gin_trgm_ops);
@@ -0,0 +1,14 @@
+-- branded short domains; a link lives on one of its owner's domains, or on base_url without one
+CREATE TABLE IF NOT EXISTS domains (
+    id BIGSERIAL PRIMARY KEY,
+    owner_id BIGINT NOT NULL REFERENCES owners(id),
+    -- lower case, without a port; matched against the request's Host header
+    host TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS domain_id BIGINT NULL REFERENCES domains(id);
+
+-- the same code may exist once per domain; base_url counts as domain 0
+ALTER TABLE urls DROP CONSTRAINT IF EXISTS urls_short_code_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_urls_domain_code ON urls (COALESCE(domain_id, 0), short_code);
@@ -0,0 +1,2 @@
+-- query parameters of the short url are merged into the destination on redirect
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS query_passthrough BOOLEAN NOT NULL DEFAULT FALSE;
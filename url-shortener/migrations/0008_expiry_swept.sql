@@ -0,0 +1,3 @@
+-- set once the sweep has evicted an expired link and announced it; the link itself stays
+-- live in the listing as expired rather than deleted
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS expiry_swept BOOLEAN NOT NULL DEFAULT FALSE;
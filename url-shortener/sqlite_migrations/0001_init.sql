@@ -0,0 +1,66 @@
+-- the postgres schema of migrations/0001..0004 for the embedded backend; timestamps are
+-- RFC 3339 text in UTC, always written by the app, so they compare correctly as strings
+CREATE TABLE IF NOT EXISTS owners (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY,
+    owner_id INTEGER NOT NULL REFERENCES owners(id),
+    -- sha256 hex of the bearer token, the token itself is never stored
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT NULL
+);
+
+CREATE TABLE IF NOT EXISTS urls (
+    id INTEGER PRIMARY KEY,
+    short_code TEXT NOT NULL UNIQUE,
+    original_url TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NULL,
+    is_deleted INTEGER NOT NULL DEFAULT 0,
+    clicks INTEGER NOT NULL DEFAULT 0,
+    owner_id INTEGER NULL REFERENCES owners(id),
+    redirect_status INTEGER NOT NULL DEFAULT 302 CHECK (redirect_status IN (301, 302, 307, 308)),
+    password_hash TEXT NULL,
+    max_clicks INTEGER NULL CHECK (max_clicks > 0),
+    uses INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_urls_original_url ON urls (original_url);
+CREATE INDEX IF NOT EXISTS idx_urls_owner_created ON urls (owner_id, created_at DESC, id DESC);
+
+CREATE TABLE IF NOT EXISTS link_targets (
+    id INTEGER PRIMARY KEY,
+    url_id INTEGER NOT NULL REFERENCES urls(id),
+    target_url TEXT NOT NULL,
+    weight INTEGER NOT NULL DEFAULT 1 CHECK (weight >= 0),
+    country TEXT NULL CHECK (length(country) = 2 AND country = upper(country)),
+    device TEXT NULL CHECK (device IN ('mobile', 'tablet', 'desktop')),
+    is_deleted INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_targets_url_id ON link_targets (url_id) WHERE NOT is_deleted;
+
+CREATE TABLE IF NOT EXISTS clicks (
+    id INTEGER PRIMARY KEY,
+    url_id INTEGER NOT NULL REFERENCES urls(id),
+    created_at TEXT NOT NULL,
+    ip TEXT NULL,
+    user_agent TEXT NULL,
+    referer TEXT NULL,
+    target_id INTEGER NULL REFERENCES link_targets(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_clicks_url_id_created_at ON clicks (url_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_clicks_target_id ON clicks (target_id) WHERE target_id IS NOT NULL;
+
+-- stands in for postgres' short_code_seq; starts at 62^3 so codes are at least four characters
+CREATE TABLE IF NOT EXISTS short_code_seq (
+    next_value INTEGER NOT NULL
+);
+INSERT INTO short_code_seq (next_value) SELECT 238328 WHERE NOT EXISTS (SELECT 1 FROM short_code_seq);
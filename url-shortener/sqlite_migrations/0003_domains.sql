@@ -0,0 +1,37 @@
+-- migrations/0006_domains.sql; sqlite cannot drop the inline unique constraint on short_code,
+-- so urls is rebuilt. Foreign keys are checked at commit, once the rows are back.
+CREATE TABLE IF NOT EXISTS domains (
+    id INTEGER PRIMARY KEY,
+    owner_id INTEGER NOT NULL REFERENCES owners(id),
+    host TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TEMP TABLE urls_backup AS SELECT * FROM urls;
+DROP TABLE urls;
+
+CREATE TABLE urls (
+    id INTEGER PRIMARY KEY,
+    short_code TEXT NOT NULL,
+    original_url TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NULL,
+    is_deleted INTEGER NOT NULL DEFAULT 0,
+    clicks INTEGER NOT NULL DEFAULT 0,
+    owner_id INTEGER NULL REFERENCES owners(id),
+    redirect_status INTEGER NOT NULL DEFAULT 302 CHECK (redirect_status IN (301, 302, 307, 308)),
+    password_hash TEXT NULL,
+    max_clicks INTEGER NULL CHECK (max_clicks > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    domain_id INTEGER NULL REFERENCES domains(id)
+);
+
+INSERT INTO urls (id, short_code, original_url, created_at, expires_at, is_deleted, clicks, owner_id, redirect_status, password_hash, max_clicks, uses)
+SELECT id, short_code, original_url, created_at, expires_at, is_deleted, clicks, owner_id, redirect_status, password_hash, max_clicks, uses FROM urls_backup;
+DROP TABLE urls_backup;
+
+CREATE INDEX IF NOT EXISTS idx_urls_original_url ON urls (original_url);
+CREATE INDEX IF NOT EXISTS idx_urls_owner_created ON urls (owner_id, created_at DESC, id DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_urls_domain_code ON urls (COALESCE(domain_id, 0), short_code);
@@ -0,0 +1,2 @@
+-- migrations/0007_query_passthrough.sql
+ALTER TABLE urls ADD COLUMN query_passthrough INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- migrations/0008_expiry_swept.sql
+ALTER TABLE urls ADD COLUMN expiry_swept INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::api::{insert_link, prepare_create, AppState, CreateReq};
+use crate::config::Config;
+use crate::domains::{is_valid_host, normalize_host};
+use crate::redis_queue::{link_cache_key, LINK_CACHE_PREFIX};
+use crate::stats;
+use crate::store;
+use crate::webhooks;
+
+pub const USAGE: &str = "usage: shorty [--config <file.toml>] <command>
+commands:
+  serve                                          run the api (the default)
+  worker                                         drain the click queue into the database
+  migrate                                        apply pending migrations and exit
+  create <url> [--alias <alias>] [--owner <name>] [--domain <host>]
+                                                 shorten a url, owned by \"admin\" unless --owner is given
+  disable <code> [--domain <host>]               soft-delete a link and drop it from the cache
+  purge-cache [<code> [--domain <host>]]         drop one link, or every link, from the redis cache
+  stats <code> [--days <n>] [--domain <host>]    print a link's info and click breakdown as json
+  domains add <host> --owner <name> | domains remove <host> | domains list
+                                                 manage the custom domains links can be created on
+  csv import <file> [--owner <name>] | csv export [<file>]
+  --create-api-key <owner>                       print a new api key for the owner";
+
+// links created from the cli belong to this owner unless --owner says otherwise
+const DEFAULT_OWNER: &str = "admin";
+
+/// Positional arguments and `--name value` options of a command; unknown options are an error.
+fn parse_args<'a>(args:&'a [String], options:&[&str])->anyhow::Result<(Vec<&'a str>, HashMap<&'a str, &'a str>)>{
+    let mut positional = Vec::new();
+    let mut opts = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(name) if options.contains(&name) => {
+                let value = iter.next().ok_or_else(|| anyhow::anyhow!("--{} needs a value\n{}", name, USAGE))?;
+                opts.insert(name, value.as_str());
+            }
+            Some(_) => anyhow::bail!("unknown option {}\n{}", arg, USAGE),
+            None => positional.push(arg.as_str()),
+        }
+    }
+    Ok((positional, opts))
+}
+
+// the same store and cache the api uses, with the schema brought up to date
+async fn open(config:&Config)->anyhow::Result<AppState>{
+    let state = AppState::from_config(config).await?;
+    state.store.migrate().await?;
+    state.domains.reload(state.store.as_ref()).await?;
+    Ok(state)
+}
+
+pub async fn migrate(config:&Config)->anyhow::Result<()>{
+    let store = store::connect(config).await?;
+    store.migrate().await?;
+    println!("{} schema is up to date", store.backend());
+    Ok(())
+}
+
+/// Shortens a url through the same validation and code generation as `POST /api/shorten`.
+pub async fn create(config:&Config, args:&[String])->anyhow::Result<()>{
+    let (positional, opts) = parse_args(args, &["alias", "owner", "domain"])?;
+    let [url] = positional[..] else {
+        anyhow::bail!(USAGE);
+    };
+    let state = open(config).await?;
+    let req = CreateReq{
+        url: url.to_string(),
+        custom_alias: opts.get("alias").map(|a| a.to_string()),
+        domain: opts.get("domain").map(|d| d.to_string()),
+        ..Default::default()
+    };
+    let link = prepare_create(&state, req).await?;
+    let mut tx = state.store.begin().await?;
+    let owner_id = tx.owner_id(opts.get("owner").copied().unwrap_or(DEFAULT_OWNER)).await?;
+    let (created, resp) = insert_link(tx.as_mut(), &state, owner_id, link).await?;
+    tx.commit().await?;
+    // the code may have been probed before it existed
+    if created && state.redis_pool.is_some() {
+        evict(&state, resp.domain.as_deref(), &resp.code).await;
+    }
+    if created {
+        webhooks::emit(state.store.as_ref(), &state.base_url, webhooks::LINK_CREATED, &[resp.link_ref(owner_id)]).await;
+    }
+    if !created {
+        eprintln!("the owner already has a link for this url");
+    }
+    println!("{}", resp.short_url);
+    Ok(())
+}
+
+pub async fn disable(config:&Config, args:&[String])->anyhow::Result<()>{
+    let (positional, opts) = parse_args(args, &["domain"])?;
+    let [code] = positional[..] else {
+        anyhow::bail!(USAGE);
+    };
+    let state = open(config).await?;
+    let domain = state.domains.owned(None, opts.get("domain").copied())?;
+    let Some(link) = state.store.delete_link(None, domain.map(|d| d.id), code).await? else {
+        anyhow::bail!("no live link with code {}", code);
+    };
+    let host = link.domain.clone();
+    webhooks::emit(state.store.as_ref(), &state.base_url, webhooks::LINK_DELETED, &[link]).await;
+    match state.redis_pool {
+        Some(_) => evict(&state, host.as_deref(), code).await,
+        None => eprintln!("no redis_url: a running api keeps serving its cached copy of {} until the entry expires or it restarts", code),
+    }
+    println!("disabled {}", code);
+    Ok(())
+}
+
+pub async fn purge_cache(config:&Config, args:&[String])->anyhow::Result<()>{
+    let (positional, opts) = parse_args(args, &["domain"])?;
+    let state = AppState::from_config(config).await?;
+    if state.redis_pool.is_none() {
+        anyhow::bail!("no redis_url: the cache lives in the api process, restart it to clear the cache");
+    }
+    let host = opts.get("domain").map(|d| normalize_host(d));
+    match positional[..] {
+        [code] => {
+            state.cache.shared().del(&[link_cache_key(host.as_deref(), code)]).await?;
+            println!("purged {}", code);
+        }
+        [] if host.is_none() => {
+            let removed = state.cache.shared().del_prefix(LINK_CACHE_PREFIX).await?;
+            println!("purged {} cached links", removed);
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+    Ok(())
+}
+
+pub async fn stats(config:&Config, args:&[String])->anyhow::Result<()>{
+    let (positional, opts) = parse_args(args, &["days", "domain"])?;
+    let [code] = positional[..] else {
+        anyhow::bail!(USAGE);
+    };
+    let days = match opts.get("days") {
+        Some(days) => Some(days.parse().map_err(|e| anyhow::anyhow!("--days {:?}: {}", days, e))?),
+        None => None,
+    };
+    let state = open(config).await?;
+    let domain = state.domains.owned(None, opts.get("domain").copied())?;
+    let stats = stats::link_stats(state.store.as_ref(), None, domain.map(|d| d.id), code, days)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no link with code {}", code))?;
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+    Ok(())
+}
+
+pub async fn domains(config:&Config, args:&[String])->anyhow::Result<()>{
+    let (positional, opts) = parse_args(args, &["owner"])?;
+    match positional[..] {
+        ["add", host] => {
+            let Some(owner) = opts.get("owner") else {
+                anyhow::bail!(USAGE);
+            };
+            let host = normalize_host(host);
+            if !is_valid_host(&host) {
+                anyhow::bail!("{:?} is not a valid host name", host);
+            }
+            // links on base_url already answer for its host
+            if url::Url::parse(&config.base_url).ok().and_then(|u| u.host_str().map(normalize_host)).as_deref() == Some(host.as_str()) {
+                anyhow::bail!("{} is the host of base_url", host);
+            }
+            let state = open(config).await?;
+            let Some(domain) = state.store.create_domain(owner, &host).await? else {
+                anyhow::bail!("{} is already registered", host);
+            };
+            println!("added {} for {}", domain.host, owner);
+        }
+        ["remove", host] if opts.is_empty() => {
+            let host = normalize_host(host);
+            let state = open(config).await?;
+            if !state.store.delete_domain(&host).await? {
+                anyhow::bail!("no domain {}, or links were created on it", host);
+            }
+            println!("removed {}", host);
+        }
+        ["list"] if opts.is_empty() => {
+            let state = open(config).await?;
+            println!("{}", serde_json::to_string_pretty(&state.store.list_domains(None).await?)?);
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+    Ok(())
+}
+
+// the database change already happened, so a redis failure only warns with what to run later
+async fn evict(state:&AppState, domain:Option<&str>, code:&str){
+    if let Err(e) = state.cache.shared().del(&[link_cache_key(domain, code)]).await {
+        let flag = domain.map(|d| format!(" --domain {}", d)).unwrap_or_default();
+        eprintln!("could not drop {} from the cache, run `shorty purge-cache {}{}` once redis is back: {:#}", code, code, flag, e);
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::config::Config;
+
+pub const MIN_ALIAS_LEN: usize = 3;
+// matches urls.short_code VARCHAR(12)
+pub const MAX_ALIAS_LEN: usize = 12;
+
+// first path segments the router serves itself; an alias equal to one of these would be unreachable or confusing
+const ROUTE_WORDS: &[&str] = &["api", "admin", "healthz", "readyz", "metrics", "static", "assets"];
+
+#[derive(Debug, Error)]
+pub enum AliasError {
+    #[error("alias must be between {MIN_ALIAS_LEN} and {MAX_ALIAS_LEN} characters")]
+    Length,
+
+    #[error("alias may only contain letters, digits, '-' and '_'")]
+    Charset,
+
+    #[error("alias {0:?} is reserved")]
+    Reserved(String),
+}
+
+impl AliasError {
+    pub fn rule(&self) -> &'static str {
+        match self {
+            AliasError::Length => "length",
+            AliasError::Charset => "charset",
+            AliasError::Reserved(_) => "reserved",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AliasPolicy{
+    reserved: HashSet<String>,
+}
+
+impl AliasPolicy {
+    /// Route words are always reserved; `reserved_aliases` adds more.
+    pub fn from_config(config:&Config)->Self{
+        Self::new(config.reserved_aliases.iter().map(String::as_str))
+    }
+
+    pub fn new<'a>(extra: impl IntoIterator<Item = &'a str>)->Self{
+        let reserved = ROUTE_WORDS.iter().copied()
+            .chain(extra)
+            .map(str::to_ascii_lowercase)
+            .collect();
+        AliasPolicy{reserved}
+    }
+
+    pub fn check(&self, alias:&str)->Result<(), AliasError>{
+        if !(MIN_ALIAS_LEN..=MAX_ALIAS_LEN).contains(&alias.len()) {
+            return Err(AliasError::Length);
+        }
+        if !alias.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(AliasError::Charset);
+        }
+        if self.is_reserved(alias) {
+            return Err(AliasError::Reserved(alias.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether `code` is a route word or a reserved alias; generated codes are held to this too.
+    pub fn is_reserved(&self, code:&str)->bool{
+        self.reserved.contains(&code.to_ascii_lowercase())
+    }
+}
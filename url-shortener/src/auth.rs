@@ -0,0 +1,48 @@
+use axum::{
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use sha2::{Digest, Sha256};
+
+use crate::api::AppState;
+use crate::errors::AppError;
+use crate::store::LinkStore;
+
+const KEY_PREFIX: &str = "sk_";
+const KEY_RANDOM_LEN: usize = 32;
+
+/// The owner behind the bearer token of the current `/api/*` request.
+#[derive(Clone, Copy, Debug)]
+pub struct Owner{
+    pub id: i64,
+    /// the api key that authenticated the request
+    pub key_id: i64,
+}
+
+pub async fn require_api_key(Extension(state): Extension<AppState>, mut req: Request, next: Next) -> Result<Response, AppError> {
+    let token = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(AppError::Unauthorized)?;
+    let owner = state.store.api_key_owner(&hash_key(token))
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    req.extensions_mut().insert(owner);
+    Ok(next.run(req).await)
+}
+
+/// Creates the owner if needed and returns a freshly minted key; only its hash is stored.
+pub async fn create_api_key(store:&dyn LinkStore, owner_name:&str)->anyhow::Result<String>{
+    let token = format!("{}{}", KEY_PREFIX, crate::codegen::random_base62(KEY_RANDOM_LEN));
+    store.create_api_key(owner_name, &hash_key(&token)).await?;
+    Ok(token)
+}
+
+fn hash_key(token:&str)->String{
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
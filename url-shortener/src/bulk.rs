@@ -0,0 +1,86 @@
+use axum::{http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::{evict_cached_link, insert_link, prepare_create, AppState, CreateReq};
+use crate::auth::Owner;
+use crate::errors::AppError;
+use crate::metrics::METRICS;
+use crate::webhooks;
+
+#[derive(Deserialize)]
+pub struct BulkReq{
+    pub items: Vec<CreateReq>,
+}
+
+#[derive(Serialize)]
+pub struct BulkResp{
+    /// one entry per request item, in request order
+    results: Vec<serde_json::Value>,
+}
+
+/// Creates every item in one transaction. Item-level failures are reported in place;
+/// a database error rolls back the whole batch.
+pub async fn create_bulk(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Json(payload): Json<BulkReq>) -> Result<Json<BulkResp>, AppError> {
+    if payload.items.is_empty() {
+        return Err(AppError::BadRequest("items must not be empty".into()));
+    }
+    if payload.items.len() > state.bulk_max_items {
+        return Err(AppError::BadRequest(format!("at most {} items per request", state.bulk_max_items)));
+    }
+
+    // every item costs a creation from the caller's limit, items past it are refused in place;
+    // passwords are hashed before the transaction opens so it is not held across the slow part
+    let mut prepared = Vec::with_capacity(payload.items.len());
+    for item in payload.items {
+        let link = match state.shorten_limit.check_owner(&owner).await {
+            Ok(()) => prepare_create(&state, item).await,
+            Err(e) => Err(e),
+        };
+        prepared.push(link);
+    }
+
+    let mut tx = state.store.begin().await?;
+    let mut results = Vec::with_capacity(prepared.len());
+    let mut created_links = Vec::new();
+    for link in prepared {
+        let outcome = match link {
+            Ok(link) => insert_link(tx.as_mut(), &state, owner.id, link).await,
+            Err(e) => Err(e),
+        };
+        match &outcome {
+            // created links are counted once the transaction commits
+            Ok((true, _)) => {}
+            Ok((false, _)) => METRICS.link_created("existing"),
+            Err(e) => METRICS.link_created(e.kind()),
+        }
+        let result = match outcome {
+            Ok((created, resp)) => {
+                let status = if created {
+                    created_links.push(resp.link_ref(owner.id));
+                    StatusCode::CREATED
+                } else {
+                    StatusCode::OK
+                };
+                let mut value = json!(resp);
+                value["status"] = json!(status.as_u16());
+                value
+            }
+            Err(e @ AppError::Db(_)) => return Err(e),
+            Err(e) => {
+                let mut value = e.body();
+                value["status"] = json!(e.status().as_u16());
+                value
+            }
+        };
+        results.push(result);
+    }
+    tx.commit().await?;
+    // drop negative cache entries for codes that exist now
+    for link in &created_links {
+        METRICS.link_created("created");
+        evict_cached_link(&state, link.domain.as_deref(), &link.code).await;
+    }
+    webhooks::emit(state.store.as_ref(), &state.base_url, webhooks::LINK_CREATED, &created_links).await;
+    Ok(Json(BulkResp{results}))
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{http::StatusCode, response::IntoResponse};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::db;
+use crate::domains::Domain;
+use crate::link_cache::LinkCache;
+use crate::metrics::METRICS;
+use crate::store::{LinkRef, LinkStore};
+use crate::targets::LinkTarget;
+use crate::redis_queue::link_cache_key;
+
+// how long the shared tier is skipped after a failure before it is tried again
+const REDIS_COOLDOWN: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedLink{
+    pub id: i64,
+    pub url: String,
+    pub status: u16,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub targets: Vec<LinkTarget>,
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+    #[serde(default)]
+    pub query_passthrough: bool,
+}
+
+// what we keep under the link's cache key in the shared and the local tier; misses are cached too
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum CacheEntry{
+    Live(CachedLink),
+    NotFound,
+    Deleted,
+    Expired,
+}
+
+impl CacheEntry {
+    fn into_result(self)->Result<CachedLink, LookupError>{
+        match self {
+            // the cached copy may outlive the link by up to the local ttl
+            CacheEntry::Live(link) if link.expires_at.is_some_and(|exp| exp <= chrono::Utc::now()) => Err(LookupError::Expired),
+            CacheEntry::Live(link) => Ok(link),
+            CacheEntry::NotFound => Err(LookupError::NotFound),
+            CacheEntry::Deleted => Err(LookupError::Deleted),
+            CacheEntry::Expired => Err(LookupError::Expired),
+        }
+    }
+}
+
+/// Why a code does not resolve to a live link.
+pub enum LookupError{
+    NotFound,
+    Deleted,
+    Expired,
+    /// the request's host is neither base_url nor a known custom domain
+    UnknownHost,
+    /// custom domains are not loaded yet, so a host other than base_url cannot be placed
+    DomainsLoading,
+    Db(Arc<sqlx::Error>),
+}
+
+impl IntoResponse for LookupError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            LookupError::NotFound => (StatusCode::NOT_FOUND, "not found").into_response(),
+            LookupError::Deleted => (StatusCode::NOT_FOUND, "deleted").into_response(),
+            LookupError::Expired => (StatusCode::NOT_FOUND, "expired").into_response(),
+            LookupError::UnknownHost => (StatusCode::NOT_FOUND, "unknown domain").into_response(),
+            LookupError::DomainsLoading => (StatusCode::SERVICE_UNAVAILABLE, "domains not loaded yet").into_response(),
+            LookupError::Db(e) if db::is_unavailable(&e) => {
+                tracing::error!("db unavailable: {:?}", e);
+                (StatusCode::SERVICE_UNAVAILABLE, "database unavailable").into_response()
+            }
+            LookupError::Db(e) => {
+                tracing::error!("db error: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+            }
+        }
+    }
+}
+
+type Flight = Arc<OnceCell<Result<CacheEntry, Arc<sqlx::Error>>>>;
+
+/// Redirect lookups go through a small in-process LRU, then the shared tier (redis), then the
+/// store. Concurrent store lookups for the same code share one query, and not-found, deleted
+/// and expired answers are cached briefly so unknown codes do not reach the store on every
+/// request. While the store is down, live links still in the local tier are served past their
+/// local ttl; while the shared tier is down, it is skipped for a short cooldown instead of being
+/// retried per request.
+pub struct RedirectCache{
+    shared: Arc<dyn LinkCache>,
+    shared_down_until: Mutex<Option<Instant>>,
+    local: Mutex<LruCache<String, (Instant, CacheEntry)>>,
+    inflight: Mutex<HashMap<String, Flight>>,
+    local_ttl: Duration,
+    /// shared tier ttl of live links, shortened to what is left of a link's lifetime
+    live_ttl: u64,
+    negative_ttl: u64,
+}
+
+impl RedirectCache {
+    pub fn from_config(shared:Arc<dyn LinkCache>, config:&Config)->anyhow::Result<Self>{
+        let capacity = NonZeroUsize::new(config.local_cache_capacity).ok_or_else(|| anyhow::anyhow!("local_cache_capacity must be at least 1"))?;
+        Ok(RedirectCache{
+            shared,
+            shared_down_until: Mutex::new(None),
+            local: Mutex::new(LruCache::new(capacity)),
+            inflight: Mutex::new(HashMap::new()),
+            local_ttl: Duration::from_secs(config.local_cache_ttl_secs),
+            live_ttl: config.cache_ttl_secs,
+            negative_ttl: config.negative_cache_ttl_secs,
+        })
+    }
+
+    /// Looks a code up on a custom domain, or on base_url when `domain` is `None`.
+    pub async fn resolve(&self, store:&dyn LinkStore, domain:Option<&Domain>, code:&str)->Result<CachedLink, LookupError>{
+        let key = link_cache_key(domain.map(|d| d.host.as_str()), code);
+        if let Some(entry) = self.local_get(&key) {
+            METRICS.cache_lookup("local", "hit");
+            return entry.into_result();
+        }
+        METRICS.cache_lookup("local", "miss");
+        if let Some(entry) = self.shared_get(&key).await {
+            self.local_put(&key, &entry);
+            return entry.into_result();
+        }
+
+        let flight = self.inflight.lock().expect("inflight lock poisoned")
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let result = flight.get_or_init(|| self.load(store, domain.map(|d| d.id), code, &key)).await.clone();
+        {
+            let mut inflight = self.inflight.lock().expect("inflight lock poisoned");
+            if inflight.get(&key).is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+                inflight.remove(&key);
+            }
+        }
+        match result {
+            Ok(entry) => entry.into_result(),
+            Err(e) => match self.local_stale(&key) {
+                Some(link) => {
+                    tracing::warn!("db error, serving stale cache entry for {}: {}", key, e);
+                    Ok(link)
+                }
+                None => Err(LookupError::Db(e)),
+            },
+        }
+    }
+
+    /// Drops a code from both tiers; other api instances keep their local copy for at most the local ttl.
+    pub async fn evict(&self, domain:Option<&str>, code:&str){
+        let key = link_cache_key(domain, code);
+        self.local.lock().expect("local cache lock poisoned").pop(&key);
+        if let Err(e) = self.shared.del(&[key]).await {
+            tracing::warn!("failed to evict {} from cache: {:?}", code, e);
+        }
+    }
+
+    /// `evict` for many links with a single round trip to the shared tier.
+    pub async fn evict_many(&self, links:&[LinkRef]){
+        let keys: Vec<String> = links.iter().map(|l| link_cache_key(l.domain.as_deref(), &l.code)).collect();
+        {
+            let mut local = self.local.lock().expect("local cache lock poisoned");
+            for key in &keys {
+                local.pop(key);
+            }
+        }
+        if let Err(e) = self.shared.del(&keys).await {
+            tracing::warn!("failed to evict {} links from cache: {:?}", links.len(), e);
+        }
+    }
+
+    // runs once per key no matter how many requests are waiting on it
+    async fn load(&self, store:&dyn LinkStore, domain_id:Option<i64>, code:&str, key:&str)->Result<CacheEntry, Arc<sqlx::Error>>{
+        let row = store.find_link(domain_id, code)
+            .await
+            .map_err(|e| {
+                METRICS.db_lookups.with_label_values(&["error"]).inc();
+                Arc::new(e)
+            })?;
+        let (entry, ttl) = match row {
+            None => (CacheEntry::NotFound, self.negative_ttl),
+            Some(row) if row.is_deleted => (CacheEntry::Deleted, self.negative_ttl),
+            Some(row) if row.expires_at.is_some_and(|exp| exp <= chrono::Utc::now()) => (CacheEntry::Expired, self.negative_ttl),
+            // a used-up link reads as expired until its limit is raised
+            Some(row) if row.max_clicks.is_some_and(|max| row.uses >= max) => (CacheEntry::Expired, self.negative_ttl),
+            // never cache past the link's own expiry
+            Some(row) => {
+                let targets = store.link_targets(row.id).await.map_err(|e| {
+                    METRICS.db_lookups.with_label_values(&["error"]).inc();
+                    Arc::new(e)
+                })?;
+                (CacheEntry::Live(CachedLink{
+                    id: row.id,
+                    url: row.original_url,
+                    status: row.redirect_status as u16,
+                    expires_at: row.expires_at,
+                    targets,
+                    password_hash: row.password_hash,
+                    max_clicks: row.max_clicks,
+                    query_passthrough: row.query_passthrough,
+                }), live_ttl(row.expires_at, self.live_ttl))
+            }
+        };
+        let result = match &entry {
+            CacheEntry::Live(_) => "live",
+            CacheEntry::NotFound => "not_found",
+            CacheEntry::Deleted => "deleted",
+            CacheEntry::Expired => "expired",
+        };
+        METRICS.db_lookups.with_label_values(&[result]).inc();
+        if ttl > 0 {
+            self.shared_put(key, &entry, ttl).await;
+            self.local_put(key, &entry);
+        }
+        Ok(entry)
+    }
+
+    fn local_get(&self, key:&str)->Option<CacheEntry>{
+        let mut local = self.local.lock().expect("local cache lock poisoned");
+        match local.get(key) {
+            Some((until, entry)) if *until > Instant::now() => Some(entry.clone()),
+            _ => None,
+        }
+    }
+
+    // expired local entries stay until the lru drops them, as a fallback while the store is down
+    fn local_stale(&self, key:&str)->Option<CachedLink>{
+        let mut local = self.local.lock().expect("local cache lock poisoned");
+        match local.get(key) {
+            Some((_, CacheEntry::Live(link))) => CacheEntry::Live(link.clone()).into_result().ok(),
+            _ => None,
+        }
+    }
+
+    fn local_put(&self, key:&str, entry:&CacheEntry){
+        let mut ttl = self.local_ttl;
+        if let CacheEntry::Live(link) = entry && let Some(exp) = link.expires_at {
+            ttl = ttl.min((exp - chrono::Utc::now()).to_std().unwrap_or_default());
+        }
+        if ttl.is_zero() {
+            return;
+        }
+        self.local.lock().expect("local cache lock poisoned").put(key.to_string(), (Instant::now() + ttl, entry.clone()));
+    }
+
+    async fn shared_get(&self, key:&str)->Option<CacheEntry>{
+        let tier = self.shared.backend();
+        if !self.shared_available() {
+            METRICS.cache_lookup(tier, "unavailable");
+            return None;
+        }
+        match self.shared.get(key).await {
+            Ok(raw) => {
+                self.shared_succeeded();
+                let entry = raw.and_then(|raw| serde_json::from_str(&raw).ok());
+                METRICS.cache_lookup(tier, if entry.is_some() { "hit" } else { "miss" });
+                entry
+            }
+            Err(e) => {
+                METRICS.cache_lookup(tier, "unavailable");
+                self.shared_failed(e);
+                None
+            }
+        }
+    }
+
+    async fn shared_put(&self, key:&str, entry:&CacheEntry, ttl:u64){
+        if !self.shared_available() {
+            return;
+        }
+        let Ok(raw) = serde_json::to_string(entry) else {
+            return;
+        };
+        match self.shared.set(key, raw, ttl).await {
+            Ok(()) => self.shared_succeeded(),
+            Err(e) => self.shared_failed(e),
+        }
+    }
+
+    /// The shared tier, for readiness checks and pool metrics.
+    pub fn shared(&self)->&dyn LinkCache{
+        self.shared.as_ref()
+    }
+
+    fn shared_available(&self)->bool{
+        self.shared_down_until.lock().expect("shared tier state lock poisoned")
+            .is_none_or(|until| until <= Instant::now())
+    }
+
+    fn shared_succeeded(&self){
+        if self.shared_down_until.lock().expect("shared tier state lock poisoned").take().is_some() {
+            tracing::info!("{} is reachable again", self.shared.backend());
+        }
+    }
+
+    fn shared_failed(&self, e:anyhow::Error){
+        let mut down_until = self.shared_down_until.lock().expect("shared tier state lock poisoned");
+        if down_until.is_none() {
+            tracing::warn!("{} unavailable, redirects fall back to the store: {:#}", self.shared.backend(), e);
+        }
+        *down_until = Some(Instant::now() + REDIS_COOLDOWN);
+    }
+}
+
+fn live_ttl(expires_at:Option<chrono::DateTime<chrono::Utc>>, max:u64)->u64{
+    match expires_at {
+        Some(exp) => ((exp - chrono::Utc::now()).num_seconds().max(0) as u64).min(max),
+        None => max,
+    }
+}
@@ -0,0 +1,86 @@
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::store::LinkTx;
+
+const CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+pub const DEFAULT_CODE_LENGTH: usize = 7;
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// How short codes are produced for links created without a custom alias.
+#[derive(Clone, Debug)]
+pub enum CodeStrategy{
+    /// Random base62 characters from the OS rng.
+    Random{len: usize},
+    /// Base62 of the next value of `short_code_seq`; codes grow as the sequence does.
+    Sequence,
+    /// Base62 of a SHA-256 of the URL, salted with a random nonce on retries so a URL is not
+    /// limited to MAX_ATTEMPTS codes.
+    Hash{len: usize},
+}
+
+impl CodeStrategy {
+    pub fn from_config(config:&Config)->anyhow::Result<Self>{
+        Self::from_name(&config.code_strategy, config.code_length)
+    }
+
+    pub fn from_name(name:&str, len:usize)->anyhow::Result<Self>{
+        if !(4..=12).contains(&len) {
+            anyhow::bail!("code_length must be between 4 and 12, got {}", len);
+        }
+        match name {
+            "random" => Ok(CodeStrategy::Random{len}),
+            "sequence" => Ok(CodeStrategy::Sequence),
+            "hash" => Ok(CodeStrategy::Hash{len}),
+            other => anyhow::bail!("unknown code_strategy {:?}, expected random, sequence or hash", other),
+        }
+    }
+
+    pub async fn generate(&self, tx:&mut dyn LinkTx, url:&str, attempt:u32)->Result<String, sqlx::Error>{
+        match self {
+            CodeStrategy::Random{len} => Ok(random_base62(*len)),
+            CodeStrategy::Sequence => Ok(base62(tx.next_sequence().await? as u64)),
+            CodeStrategy::Hash{len} => Ok(hash_base62(url, attempt, *len)),
+        }
+    }
+}
+
+pub fn random_base62(len: usize) -> String {
+    let mut buf = vec![0u8; len];
+    let mut rng = rand::rngs::OsRng;
+    use rand::RngCore;
+    rng.fill_bytes(&mut buf);
+    buf.iter().map(|b|CHARS[(*b as usize)%CHARS.len()] as char).collect()
+}
+
+fn base62(mut n: u64) -> String {
+    let mut out = Vec::new();
+    loop {
+        out.push(CHARS[(n % 62) as usize]);
+        n /= 62;
+        if n == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    String::from_utf8(out).expect("base62 alphabet is ascii")
+}
+
+fn hash_base62(url: &str, attempt: u32, len: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    // the first attempt stays deterministic, retries would otherwise collide with the same
+    // codes every time once the url has that many links
+    if attempt > 0 {
+        use rand::RngCore;
+        hasher.update(rand::rngs::OsRng.next_u64().to_be_bytes());
+    }
+    let digest = hasher.finalize();
+    let mut n = u128::from_be_bytes(digest[..16].try_into().expect("sha256 digest is 32 bytes"));
+    (0..len).map(|_| {
+        let c = CHARS[(n % 62) as usize] as char;
+        n /= 62;
+        c
+    }).collect()
+}
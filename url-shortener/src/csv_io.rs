@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use argon2::PasswordHash;
+use serde::{Deserialize, Serialize};
+
+use crate::alias::AliasPolicy;
+use crate::api::parse_redirect_status;
+use crate::config::Config;
+use crate::domains::{normalize_host, Domain, DomainMap};
+use crate::store::LinkStore;
+use crate::url_policy::UrlPolicy;
+
+/// One `urls` row as it appears in the CSV; only `short_code` and `original_url` are required on import.
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub(crate) struct CsvLink{
+    pub(crate) short_code: String,
+    pub(crate) original_url: String,
+    #[serde(default)]
+    pub(crate) created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub(crate) expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub(crate) is_deleted: Option<bool>,
+    #[serde(default)]
+    pub(crate) clicks: Option<i64>,
+    #[serde(default)]
+    pub(crate) owner: Option<String>,
+    #[serde(default)]
+    pub(crate) redirect_status: Option<i16>,
+    /// custom domain host; base_url when empty
+    #[serde(default)]
+    pub(crate) domain: Option<String>,
+    /// argon2 phc string, carried over as is so the link keeps its password
+    #[serde(default)]
+    pub(crate) password_hash: Option<String>,
+    #[serde(default)]
+    pub(crate) max_clicks: Option<i64>,
+    /// redirects already taken from max_clicks
+    #[serde(default)]
+    pub(crate) uses: Option<i64>,
+    #[serde(default)]
+    pub(crate) query_passthrough: Option<bool>,
+}
+
+#[derive(Default, Debug)]
+pub struct ImportReport{
+    pub imported: usize,
+    pub existing: usize,
+    pub rejected: usize,
+}
+
+/// Imports links keeping their codes. Rows whose code already exists are left untouched and
+/// invalid rows are reported and skipped; the whole file is applied in one transaction.
+pub async fn import(store:&dyn LinkStore, config:&Config, path:&str, default_owner:Option<&str>)->anyhow::Result<ImportReport>{
+    let mut reader = csv::Reader::from_path(path)?;
+    let policy = AliasPolicy::from_config(config);
+    let own_domains = Arc::new(DomainMap::new(&config.base_url));
+    own_domains.reload(store).await?;
+    let url_policy = UrlPolicy::from_config(config, own_domains)?;
+    let mut owners: HashMap<String, i64> = HashMap::new();
+    let mut domains: HashMap<String, Option<Domain>> = HashMap::new();
+    let mut report = ImportReport::default();
+
+    let mut tx = store.begin().await?;
+    for (line, record) in reader.deserialize::<CsvLink>().enumerate() {
+        // header is line 1
+        let line = line + 2;
+        let mut row = match record {
+            Ok(row) => row,
+            Err(e) => {
+                tracing::warn!("line {}: {}", line, e);
+                report.rejected += 1;
+                continue;
+            }
+        };
+        if let Err(e) = policy.check(&row.short_code) {
+            tracing::warn!("line {}: {}", line, e);
+            report.rejected += 1;
+            continue;
+        }
+        let url = match url_policy.check(&row.original_url) {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::warn!("line {}: {}", line, e);
+                report.rejected += 1;
+                continue;
+            }
+        };
+        if let Some(status) = row.redirect_status && parse_redirect_status(status as u16).is_err() {
+            tracing::warn!("line {}: unsupported redirect_status {}", line, status);
+            report.rejected += 1;
+            continue;
+        }
+        // a row that cannot keep its protection is refused rather than imported open
+        if let Some(hash) = &row.password_hash && PasswordHash::new(hash).is_err() {
+            tracing::warn!("line {}: password_hash is not an argon2 hash", line);
+            report.rejected += 1;
+            continue;
+        }
+        if row.max_clicks.is_some_and(|max| max < 1) || row.uses.is_some_and(|uses| uses < 0) {
+            tracing::warn!("line {}: max_clicks must be positive and uses not negative", line);
+            report.rejected += 1;
+            continue;
+        }
+        let mut owner_id = match row.owner.as_deref().or(default_owner) {
+            Some(name) => Some(match owners.get(name) {
+                Some(id) => *id,
+                None => {
+                    let id = tx.owner_id(name).await?;
+                    owners.insert(name.to_string(), id);
+                    id
+                }
+            }),
+            None => None,
+        };
+        // a link on a custom domain belongs to the domain's owner
+        let domain_id = match row.domain.as_deref().filter(|h| !h.is_empty()) {
+            Some(raw) => {
+                let host = normalize_host(raw);
+                let domain = match domains.get(&host) {
+                    Some(domain) => domain.clone(),
+                    None => {
+                        let domain = tx.domain(&host).await?;
+                        domains.insert(host.clone(), domain.clone());
+                        domain
+                    }
+                };
+                match domain {
+                    Some(d) if owner_id.is_none_or(|id| id == d.owner_id) => {
+                        owner_id = Some(d.owner_id);
+                        Some(d.id)
+                    }
+                    Some(_) => {
+                        tracing::warn!("line {}: domain {} belongs to another owner", line, host);
+                        report.rejected += 1;
+                        continue;
+                    }
+                    None => {
+                        tracing::warn!("line {}: unknown domain {}", line, host);
+                        report.rejected += 1;
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+        row.original_url = url;
+        let inserted = tx.import_link(owner_id, domain_id, &row).await?;
+        match inserted {
+            true => report.imported += 1,
+            false => report.existing += 1,
+        }
+    }
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Writes every link, deleted ones included, to `path` or to stdout when `path` is `-`.
+pub async fn export(store:&dyn LinkStore, path:&str)->anyhow::Result<usize>{
+    let rows = store.export_links().await?;
+    let out: Box<dyn io::Write> = if path == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(std::fs::File::create(path)?)
+    };
+    let mut writer = csv::Writer::from_writer(out);
+    for row in &rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(rows.len())
+}
@@ -1,10 +1,43 @@
-use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
-
-pub async fn create_pool(database_url:&str) -> anyhow::Result<PgPool> {
-    let pool = PgPoolOptions::new()
-    .max_connections(5)
-    .connect(&database_url)
-    .await?;
-    Ok(pool)
-}
\ No newline at end of file
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::migrate::MigrateError;
+
+use crate::retry::Backoff;
+use crate::store::LinkStore;
+
+/// Connects in the background, retrying with backoff while the database is unreachable, and applies
+/// pending migrations. The returned flag is set once the schema is up to date; a migration that
+/// fails for any other reason stops the process.
+pub fn spawn_warm_up(store:Arc<dyn LinkStore>)->Arc<AtomicBool>{
+    let ready = Arc::new(AtomicBool::new(false));
+    let flag = ready.clone();
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+        loop {
+            match store.migrate().await {
+                Ok(()) => {
+                    tracing::info!("{} is reachable, schema is up to date", store.backend());
+                    flag.store(true, Ordering::Release);
+                    return;
+                }
+                Err(MigrateError::Execute(e)) if is_unavailable(&e) => {
+                    let delay = backoff.next_delay();
+                    tracing::warn!("{} unreachable, retrying in {:?}: {}", store.backend(), delay, e);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    tracing::error!("migrations failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    });
+    ready
+}
+
+/// True for errors that mean the database could not be reached rather than a bad query.
+pub fn is_unavailable(e:&sqlx::Error)->bool{
+    matches!(e, sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) | sqlx::Error::Tls(_))
+}
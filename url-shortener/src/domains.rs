@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{
+    http::{header, HeaderMap},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::api::AppState;
+use crate::auth::Owner;
+use crate::cache::LookupError;
+use crate::errors::AppError;
+use crate::store::{LinkStore, StoreResult};
+
+// how often the refresh checks whether migrations are done before the first load
+const WARM_UP_POLL: Duration = Duration::from_secs(1);
+
+/// A branded short domain of one owner. Its links only resolve for requests made on it, so
+/// the same code can exist on several domains.
+#[derive(Clone, Debug, Serialize, sqlx::FromRow)]
+pub struct Domain{
+    pub id: i64,
+    #[serde(skip)]
+    pub owner_id: i64,
+    pub host: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// `?domain=<host>` on the routes that address one of the caller's links; absent means base_url.
+#[derive(Deserialize)]
+pub struct DomainParam{
+    pub domain: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DomainsResp{
+    domains: Vec<Domain>,
+}
+
+/// Every custom domain by host. Domains are added through the cli, so each api process
+/// reloads the map every `domain_refresh_secs`.
+pub struct DomainMap{
+    /// the host of base_url, whose links are the ones without a domain
+    base_host: String,
+    hosts: RwLock<HashMap<String, Domain>>,
+    loaded: AtomicBool,
+}
+
+impl DomainMap {
+    pub fn new(base_url:&str)->Self{
+        let base_host = url::Url::parse(base_url).ok()
+            .and_then(|url| url.host_str().map(normalize_host))
+            .unwrap_or_default();
+        DomainMap{base_host, hosts: RwLock::default(), loaded: AtomicBool::new(false)}
+    }
+
+    pub async fn reload(&self, store:&dyn LinkStore)->StoreResult<()>{
+        let hosts = store.list_domains(None).await?
+            .into_iter()
+            .map(|d| (d.host.clone(), d))
+            .collect();
+        *self.hosts.write().expect("domain map lock poisoned") = hosts;
+        self.loaded.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Reloads the map in the background, starting once migrations have been applied.
+    pub fn spawn_refresh(self:Arc<Self>, store:Arc<dyn LinkStore>, db_ready:Arc<AtomicBool>, interval:Duration){
+        tokio::spawn(async move {
+            loop {
+                if !db_ready.load(Ordering::Acquire) {
+                    tokio::time::sleep(WARM_UP_POLL).await;
+                    continue;
+                }
+                if let Err(e) = self.reload(store.as_ref()).await {
+                    tracing::warn!("failed to reload custom domains: {:?}", e);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// The custom domain a request was made on; `None` means base_url. Any other host is
+    /// refused rather than served from base_url's codes: it may be a domain added since the
+    /// last reload, whose codes can belong to someone else on base_url.
+    pub fn for_request(&self, headers:&HeaderMap)->Result<Option<Domain>, LookupError>{
+        let Some(host) = headers.get(header::HOST).and_then(|h| h.to_str().ok()).map(normalize_host) else {
+            return Ok(None);
+        };
+        if host == self.base_host {
+            return Ok(None);
+        }
+        if !self.loaded.load(Ordering::Acquire) {
+            return Err(LookupError::DomainsLoading);
+        }
+        self.get(&host).map(Some).ok_or(LookupError::UnknownHost)
+    }
+
+    /// Resolves a `?domain=` or `--domain` value, which has to name one of the owner's domains.
+    pub fn owned(&self, owner_id:Option<i64>, host:Option<&str>)->Result<Option<Domain>, AppError>{
+        let Some(host) = host else {
+            return Ok(None);
+        };
+        match self.get(&normalize_host(host)) {
+            Some(domain) if owner_id.is_none_or(|id| id == domain.owner_id) => Ok(Some(domain)),
+            _ => Err(AppError::BadRequest(format!("unknown domain {:?}", host))),
+        }
+    }
+
+    /// Whether `host`, normalized, is one of the custom domains.
+    pub fn contains(&self, host:&str)->bool{
+        self.hosts.read().expect("domain map lock poisoned").contains_key(host)
+    }
+
+    fn get(&self, host:&str)->Option<Domain>{
+        self.hosts.read().expect("domain map lock poisoned").get(host).cloned()
+    }
+}
+
+/// Lower case, without the port or a trailing dot, as hosts are stored.
+pub fn normalize_host(raw:&str)->String{
+    let host = raw.trim();
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// A registrable host name: dot-separated labels of letters, digits and inner hyphens.
+pub fn is_valid_host(host:&str)->bool{
+    host.len() <= 253 && host.contains('.') && host.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63
+            && !label.starts_with('-') && !label.ends_with('-')
+            && label.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    })
+}
+
+/// The caller's custom domains.
+pub async fn list_domains(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>) -> Result<Json<DomainsResp>, AppError> {
+    let domains = state.store.list_domains(Some(owner.id)).await?;
+    Ok(Json(DomainsResp{domains}))
+}
@@ -0,0 +1,96 @@
+use axum::{http::{header, StatusCode}, response::IntoResponse, Json};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::alias::AliasError;
+use crate::url_policy::UrlError;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("db error")]
+    Db(#[from] sqlx::Error),
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    InvalidAlias(#[from] AliasError),
+
+    #[error("{0}")]
+    InvalidUrl(#[from] UrlError),
+
+    #[error("missing or invalid api key")]
+    Unauthorized,
+
+    #[error("link not found")]
+    NotFound,
+
+    #[error("rate limit exceeded, retry in {retry_after_secs}s")]
+    RateLimited{retry_after_secs: u64},
+
+    #[error("alias not available")]
+    AliasTaken,
+
+    #[error("could not allocate a short code")]
+    CodeSpaceExhausted,
+}
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Db(e) if crate::db::is_unavailable(e) => "db_unavailable",
+            AppError::Db(_) => "db_error",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InvalidAlias(_) => "invalid_alias",
+            AppError::InvalidUrl(_) => "invalid_url",
+            AppError::Unauthorized => "unauthorized",
+            AppError::NotFound => "not_found",
+            AppError::RateLimited{..} => "rate_limited",
+            AppError::AliasTaken => "alias_taken",
+            AppError::CodeSpaceExhausted => "code_space_exhausted",
+        }
+    }
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Db(e) if crate::db::is_unavailable(e) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) | AppError::InvalidAlias(_) | AppError::InvalidUrl(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::RateLimited{..} => StatusCode::TOO_MANY_REQUESTS,
+            AppError::AliasTaken => StatusCode::CONFLICT,
+            AppError::CodeSpaceExhausted => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// The JSON error object, also used for per-item errors in bulk responses.
+    pub fn body(&self) -> serde_json::Value {
+        let mut body = json!({
+            "error": self.kind(),
+            "message": self.to_string(),
+        });
+        match self {
+            AppError::InvalidAlias(e) => body["rule"] = json!(e.rule()),
+            AppError::InvalidUrl(e) => body["rule"] = json!(e.rule()),
+            _ => {}
+        }
+        body
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        if let AppError::Db(e) = &self {
+            tracing::error!("db error: {:?}", e);
+        }
+        let (status, body) = (self.status(), Json(self.body()));
+        match self {
+            AppError::Unauthorized => (status, [(header::WWW_AUTHENTICATE, "Bearer".to_string())], body).into_response(),
+            AppError::RateLimited{retry_after_secs} => (status, [(header::RETRY_AFTER, retry_after_secs.to_string())], body).into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
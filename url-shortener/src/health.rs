@@ -0,0 +1,67 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use serde::Serialize;
+
+use crate::api::AppState;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+pub struct Readiness{
+    status: &'static str,
+    /// the link store, postgres or sqlite
+    database: bool,
+    /// the shared cache, redis or in memory
+    cache: bool,
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    "ok"
+}
+
+/// Readiness: "degraded" while one of the database or the cache is down, 503 only when both are.
+pub async fn readyz(Extension(state): Extension<AppState>) -> impl IntoResponse {
+    let (database, cache) = tokio::join!(check_database(&state), check_cache(&state));
+    let (status, code) = match (database, cache) {
+        (true, true) => ("ready", StatusCode::OK),
+        (false, false) => ("unavailable", StatusCode::SERVICE_UNAVAILABLE),
+        _ => ("degraded", StatusCode::OK),
+    };
+    (code, Json(Readiness{status, database, cache}))
+}
+
+async fn check_database(state:&AppState)->bool{
+    if !state.db_ready.load(Ordering::Acquire) {
+        return false;
+    }
+    let backend = state.store.backend();
+    match tokio::time::timeout(CHECK_TIMEOUT, state.store.ping()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            tracing::warn!("readiness: {} check failed: {}", backend, e);
+            false
+        }
+        Err(_) => {
+            tracing::warn!("readiness: {} check timed out", backend);
+            false
+        }
+    }
+}
+
+async fn check_cache(state:&AppState)->bool{
+    let cache = state.cache.shared();
+    match tokio::time::timeout(CHECK_TIMEOUT, cache.ping()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            tracing::warn!("readiness: {} check failed: {}", cache.backend(), e);
+            false
+        }
+        Err(_) => {
+            tracing::warn!("readiness: {} check timed out", cache.backend());
+            false
+        }
+    }
+}
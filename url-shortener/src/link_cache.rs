@@ -0,0 +1,161 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{self, AsyncTypedCommands};
+use lru::LruCache;
+
+use crate::store::PoolState;
+
+const SCAN_COUNT: usize = 1000;
+
+/// The cache tier shared by every api instance, in front of the store. Values are opaque strings;
+/// `RedirectCache` decides what goes in them.
+#[async_trait]
+pub trait LinkCache: Send + Sync {
+    /// "redis" or "memory", the tier label of the cache metrics
+    fn backend(&self)->&'static str;
+    fn pool_state(&self)->Option<PoolState>;
+    async fn get(&self, key:&str)->anyhow::Result<Option<String>>;
+    async fn set(&self, key:&str, value:String, ttl_secs:u64)->anyhow::Result<()>;
+    async fn del(&self, keys:&[String])->anyhow::Result<()>;
+    /// Removes every key starting with `prefix` and returns how many there were.
+    async fn del_prefix(&self, prefix:&str)->anyhow::Result<usize>;
+    async fn ping(&self)->anyhow::Result<()>;
+}
+
+pub struct RedisCache{
+    pool: deadpool_redis::Pool,
+}
+
+impl RedisCache {
+    pub fn new(pool:deadpool_redis::Pool)->Self{
+        RedisCache{pool}
+    }
+}
+
+#[async_trait]
+impl LinkCache for RedisCache {
+    fn backend(&self)->&'static str{
+        "redis"
+    }
+
+    fn pool_state(&self)->Option<PoolState>{
+        let status = self.pool.status();
+        Some(PoolState{idle: status.available, size: status.size, max: status.max_size})
+    }
+
+    async fn get(&self, key:&str)->anyhow::Result<Option<String>>{
+        let mut conn = self.pool.get().await?;
+        Ok(conn.get(key).await?)
+    }
+
+    async fn set(&self, key:&str, value:String, ttl_secs:u64)->anyhow::Result<()>{
+        let mut conn = self.pool.get().await?;
+        conn.set_ex(key, value, ttl_secs).await?;
+        Ok(())
+    }
+
+    async fn del(&self, keys:&[String])->anyhow::Result<()>{
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.pool.get().await?;
+        conn.del(keys).await?;
+        Ok(())
+    }
+
+    // SCAN rather than KEYS so a large cache does not block redis
+    async fn del_prefix(&self, prefix:&str)->anyhow::Result<usize>{
+        let mut conn = self.pool.get().await?;
+        let pattern = format!("{}*", prefix);
+        let mut cursor: u64 = 0;
+        let mut removed = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut conn)
+                .await?;
+            if !keys.is_empty() {
+                removed += conn.del(keys).await?;
+            }
+            if next == 0 {
+                return Ok(removed);
+            }
+            cursor = next;
+        }
+    }
+
+    async fn ping(&self)->anyhow::Result<()>{
+        let mut conn = self.pool.get().await?;
+        conn.ping().await?;
+        Ok(())
+    }
+}
+
+/// Process-local stand-in for redis when the api runs alone; bounded, least recently used
+/// entries go first.
+pub struct MemoryCache{
+    entries: Mutex<LruCache<String, (Instant, String)>>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity:NonZeroUsize)->Self{
+        MemoryCache{entries: Mutex::new(LruCache::new(capacity))}
+    }
+}
+
+#[async_trait]
+impl LinkCache for MemoryCache {
+    fn backend(&self)->&'static str{
+        "memory"
+    }
+
+    fn pool_state(&self)->Option<PoolState>{
+        None
+    }
+
+    async fn get(&self, key:&str)->anyhow::Result<Option<String>>{
+        let mut entries = self.entries.lock().expect("memory cache lock poisoned");
+        match entries.get(key) {
+            Some((until, value)) if *until > Instant::now() => Ok(Some(value.clone())),
+            Some(_) => {
+                entries.pop(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key:&str, value:String, ttl_secs:u64)->anyhow::Result<()>{
+        let until = Instant::now() + Duration::from_secs(ttl_secs);
+        self.entries.lock().expect("memory cache lock poisoned").put(key.to_string(), (until, value));
+        Ok(())
+    }
+
+    async fn del(&self, keys:&[String])->anyhow::Result<()>{
+        let mut entries = self.entries.lock().expect("memory cache lock poisoned");
+        for key in keys {
+            entries.pop(key);
+        }
+        Ok(())
+    }
+
+    async fn del_prefix(&self, prefix:&str)->anyhow::Result<usize>{
+        let mut entries = self.entries.lock().expect("memory cache lock poisoned");
+        let keys: Vec<String> = entries.iter().map(|(key, _)| key).filter(|key| key.starts_with(prefix)).cloned().collect();
+        for key in &keys {
+            entries.pop(key);
+        }
+        Ok(keys.len())
+    }
+
+    async fn ping(&self)->anyhow::Result<()>{
+        Ok(())
+    }
+}
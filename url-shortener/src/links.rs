@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::api::{evict_cached_link, parse_redirect_status, resolve_expiry, short_url, AppState};
+use crate::auth::Owner;
+use crate::domains::DomainParam;
+use crate::errors::AppError;
+use crate::protected;
+use crate::store::{LinkUpdate, ListFilter};
+use crate::webhooks;
+
+#[derive(Deserialize)]
+pub struct UpdateReq{
+    pub url: Option<String>,
+    // absent leaves the expiry alone, null clears it
+    #[serde(default, deserialize_with = "double_option")]
+    pub expires_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    pub ttl_seconds: Option<i64>,
+    pub redirect_type: Option<u16>,
+    // absent leaves the password or limit alone, null removes it
+    #[serde(default, deserialize_with = "double_option")]
+    pub password: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub max_clicks: Option<Option<i64>>,
+    pub query_passthrough: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct LinkResp{
+    short_url: String,
+    code: String,
+    original_url: String,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    redirect_type: u16,
+    password_protected: bool,
+    max_clicks: Option<i64>,
+    uses: i64,
+    query_passthrough: bool,
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ListParams{
+    /// page size, 1..=200
+    pub limit: Option<i64>,
+    /// next_cursor of the previous page
+    pub cursor: Option<String>,
+    /// defaults to false, deleted links are hidden unless asked for
+    pub deleted: Option<bool>,
+    /// expired or used up; both are listed when absent
+    pub expired: Option<bool>,
+    /// case-insensitive substring of the destination url
+    pub q: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LinkSummary{
+    short_url: String,
+    /// custom domain host; null on base_url
+    domain: Option<String>,
+    code: String,
+    original_url: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    is_deleted: bool,
+    expired: bool,
+    clicks: i64,
+    redirect_type: u16,
+    password_protected: bool,
+    max_clicks: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct LinkPage{
+    links: Vec<LinkSummary>,
+    /// absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+// position after the last link of a page, opaque to clients
+fn encode_cursor(created_at:chrono::DateTime<chrono::Utc>, id:i64)->String{
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", created_at.timestamp_micros(), id))
+}
+
+fn decode_cursor(cursor:&str)->Option<(chrono::DateTime<chrono::Utc>, i64)>{
+    let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (micros, id) = raw.split_once(':')?;
+    Some((chrono::DateTime::from_timestamp_micros(micros.parse().ok()?)?, id.parse().ok()?))
+}
+
+// q is matched literally, not as a LIKE pattern
+fn like_pattern(q:&str)->String{
+    let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// The caller's links, newest first, one page at a time.
+pub async fn list_links(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Query(params): Query<ListParams>) -> Result<Json<LinkPage>, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    let filter = ListFilter{
+        after: params.cursor.as_deref()
+            .map(|cursor| decode_cursor(cursor).ok_or_else(|| AppError::BadRequest("invalid cursor".into())))
+            .transpose()?,
+        deleted: params.deleted.unwrap_or(false),
+        expired: params.expired,
+        pattern: params.q.as_deref().filter(|q| !q.is_empty()).map(like_pattern),
+        // one extra row tells whether there is a next page
+        limit: limit + 1,
+    };
+    let mut rows = state.store.list_links(owner.id, &filter).await?;
+
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|r| encode_cursor(r.created_at, r.id))
+    } else {
+        None
+    };
+    let links = rows.into_iter().map(|r| LinkSummary{
+        short_url: short_url(&state.base_url, r.domain.as_deref(), &r.code),
+        domain: r.domain,
+        code: r.code,
+        original_url: r.original_url,
+        created_at: r.created_at,
+        expires_at: r.expires_at,
+        is_deleted: r.is_deleted,
+        expired: r.expired,
+        clicks: r.clicks,
+        redirect_type: r.redirect_status as u16,
+        password_protected: r.password_protected,
+        max_clicks: r.max_clicks,
+    }).collect();
+    Ok(Json(LinkPage{links, next_cursor}))
+}
+
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+pub async fn delete_link(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(code): Path<String>, Query(at): Query<DomainParam>) -> Result<StatusCode, AppError> {
+    let domain = state.domains.owned(Some(owner.id), at.domain.as_deref())?;
+    let link = state.store.delete_link(Some(owner.id), domain.map(|d| d.id), &code)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    evict_cached_link(&state, link.domain.as_deref(), &code).await;
+    webhooks::emit(state.store.as_ref(), &state.base_url, webhooks::LINK_DELETED, &[link]).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn update_link(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(code): Path<String>, Query(at): Query<DomainParam>, Json(payload): Json<UpdateReq>) -> Result<Json<LinkResp>, AppError> {
+    let domain = state.domains.owned(Some(owner.id), at.domain.as_deref())?;
+    let (domain_id, host) = (domain.as_ref().map(|d| d.id), domain.map(|d| d.host));
+    let url = payload.url.as_deref().map(|raw| state.url_policy.check(raw)).transpose()?;
+    let (set_expiry, expires_at) = match (payload.expires_at, payload.ttl_seconds) {
+        (Some(None), None) => (true, None),
+        (Some(None), Some(_)) => return Err(AppError::BadRequest("set either expires_at or ttl_seconds, not both".into())),
+        (exp, ttl) if exp.is_some() || ttl.is_some() => {
+            (true, resolve_expiry(exp.flatten(), ttl).map_err(|msg| AppError::BadRequest(msg.into()))?)
+        }
+        _ => (false, None),
+    };
+    let redirect_status = payload.redirect_type.map(parse_redirect_status).transpose().map_err(|msg| AppError::BadRequest(msg.into()))?;
+    if let Some(Some(password)) = &payload.password {
+        protected::check_password(password)?;
+    }
+    if let Some(Some(max_clicks)) = payload.max_clicks {
+        protected::check_max_clicks(max_clicks)?;
+    }
+    if url.is_none() && !set_expiry && redirect_status.is_none() && payload.password.is_none() && payload.max_clicks.is_none() && payload.query_passthrough.is_none() {
+        return Err(AppError::BadRequest("nothing to update".into()));
+    }
+    let password_hash = match payload.password {
+        Some(Some(password)) => Some(Some(protected::hash_password(password).await.map_err(|e| {
+            tracing::error!("{:#}", e);
+            AppError::BadRequest("could not hash the password".into())
+        })?)),
+        Some(None) => Some(None),
+        None => None,
+    };
+
+    let update = LinkUpdate{
+        url,
+        expires_at: set_expiry.then_some(expires_at),
+        redirect_status,
+        password_hash,
+        max_clicks: payload.max_clicks,
+        query_passthrough: payload.query_passthrough,
+    };
+    let row = state.store.update_link(owner.id, domain_id, &code, &update)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    evict_cached_link(&state, host.as_deref(), &row.code).await;
+    Ok(Json(LinkResp{
+        short_url: short_url(&state.base_url, host.as_deref(), &row.code),
+        code: row.code,
+        original_url: row.original_url,
+        expires_at: row.expires_at,
+        redirect_type: row.redirect_status as u16,
+        password_protected: row.password_protected,
+        max_clicks: row.max_clicks,
+        uses: row.uses,
+        query_passthrough: row.query_passthrough,
+    }))
+}
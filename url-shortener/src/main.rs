@@ -1,14 +1,113 @@
+mod admin;
+mod alias;
 mod api;
+mod auth;
+mod bulk;
+mod cache;
+mod codegen;
+mod config;
+mod csv_io;
 mod worker;
 mod db;
+mod domains;
+mod errors;
+mod health;
+mod link_cache;
+mod links;
+mod metrics;
+mod preview;
+mod protected;
+mod proxy;
+mod qr;
+mod rate_limit;
 mod redis_queue;
+mod retry;
+mod stats;
+mod store;
+mod targets;
+mod url_policy;
+mod utm;
+mod webhooks;
+
+use config::Config;
 
 #[tokio::main]
 async fn main() ->anyhow::Result<()>{
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
     .init();
-    tracing::info!("Starting server");
-    api::run().await?;
+    let mut args: Vec<String> = std::env::args().collect();
+    let config_file = match args.iter().position(|arg| arg == "--config") {
+        Some(pos) => {
+            let Some(path) = args.get(pos + 1).cloned() else {
+                anyhow::bail!("usage: shorty --config <file.toml> ...");
+            };
+            args.drain(pos..=pos + 1);
+            Some(path)
+        }
+        None => None,
+    };
+    let config = Config::load(config_file.as_deref())?;
+    if let Some(pos) = args.iter().position(|arg| arg == "--create-api-key") {
+        let Some(owner) = args.get(pos + 1) else {
+            anyhow::bail!("usage: shorty --create-api-key <owner>");
+        };
+        let store = store::connect(&config).await?;
+        store.migrate().await?;
+        println!("{}", auth::create_api_key(store.as_ref(), owner).await?);
+        return Ok(());
+    }
+    // --worker predates the worker command
+    let command = if args.iter().any(|arg| arg == "--worker") { Some("worker") } else { args.get(1).map(String::as_str) };
+    let rest = args.get(2..).unwrap_or_default();
+    match command {
+        None | Some("serve") => {
+            tracing::info!("Starting server");
+            api::run(&config).await?;
+        }
+        Some("worker") => {
+            tracing::info!("Starting worker");
+            worker::run(&config).await?;
+        }
+        Some("migrate") => admin::migrate(&config).await?,
+        Some("create") => admin::create(&config, rest).await?,
+        Some("disable") => admin::disable(&config, rest).await?,
+        Some("purge-cache") => admin::purge_cache(&config, rest).await?,
+        Some("stats") => admin::stats(&config, rest).await?,
+        Some("domains") => admin::domains(&config, rest).await?,
+        Some("csv") => run_csv(&config, rest).await?,
+        Some("help" | "--help" | "-h") => println!("{}", admin::USAGE),
+        Some(other) => anyhow::bail!("unknown command {:?}\n{}", other, admin::USAGE),
+    }
+    Ok(())
+}
+
+const CSV_USAGE: &str = "usage: shorty csv import <file> [--owner <name>] | shorty csv export [<file>]";
+
+async fn run_csv(config:&Config, args:&[String])->anyhow::Result<()>{
+    match args.first().map(String::as_str) {
+        Some("import") => {
+            let Some(path) = args.get(1) else {
+                anyhow::bail!(CSV_USAGE);
+            };
+            let owner = match args.get(2).map(String::as_str) {
+                Some("--owner") => Some(args.get(3).ok_or_else(|| anyhow::anyhow!(CSV_USAGE))?.as_str()),
+                Some(_) => anyhow::bail!(CSV_USAGE),
+                None => None,
+            };
+            let store = store::connect(config).await?;
+            store.migrate().await?;
+            let report = csv_io::import(store.as_ref(), config, path, owner).await?;
+            println!("imported {}, already present {}, rejected {}", report.imported, report.existing, report.rejected);
+        }
+        Some("export") => {
+            let path = args.get(1).map(String::as_str).unwrap_or("-");
+            let store = store::connect(config).await?;
+            store.migrate().await?;
+            let count = csv_io::export(store.as_ref(), path).await?;
+            eprintln!("exported {} links", count);
+        }
+        _ => anyhow::bail!(CSV_USAGE),
+    }
     Ok(())
 }
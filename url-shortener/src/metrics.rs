@@ -0,0 +1,109 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry,
+    Encoder, HistogramVec, IntCounterVec, IntGaugeVec, Registry, TextEncoder,
+};
+
+use crate::api::AppState;
+
+/// Process-wide metrics, exposed in the prometheus text format on `/metrics`.
+pub struct Metrics{
+    registry: Registry,
+    /// `tier` is local, redis or memory, `result` is hit, miss or unavailable
+    pub cache_lookups: IntCounterVec,
+    /// store lookups behind the cache, by what they found
+    pub db_lookups: IntCounterVec,
+    pub redirect_seconds: HistogramVec,
+    /// create attempts by outcome: created, existing, or the error kind
+    pub links_created: IntCounterVec,
+    db_pool: IntGaugeVec,
+    redis_pool: IntGaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+    let registry = Registry::new_custom(Some("shorty".into()), None).expect("valid registry prefix");
+    Metrics{
+        cache_lookups: register_int_counter_vec_with_registry!(
+            "cache_lookups_total", "Redirect cache lookups by tier and result", &["tier", "result"], registry
+        ).expect("metric registers once"),
+        db_lookups: register_int_counter_vec_with_registry!(
+            "db_lookups_total", "Database link lookups behind the cache, by result", &["result"], registry
+        ).expect("metric registers once"),
+        redirect_seconds: register_histogram_vec_with_registry!(
+            "redirect_duration_seconds", "Time to answer a redirect, by response status", &["status"],
+            vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0], registry
+        ).expect("metric registers once"),
+        links_created: register_int_counter_vec_with_registry!(
+            "links_created_total", "Link creation attempts by outcome", &["outcome"], registry
+        ).expect("metric registers once"),
+        db_pool: register_int_gauge_vec_with_registry!(
+            "db_pool_connections", "Database pool connections by state", &["state"], registry
+        ).expect("metric registers once"),
+        redis_pool: register_int_gauge_vec_with_registry!(
+            "redis_pool_connections", "Redis pool connections by state", &["state"], registry
+        ).expect("metric registers once"),
+        registry,
+    }
+});
+
+impl Metrics {
+    pub fn cache_lookup(&self, tier:&str, result:&str){
+        self.cache_lookups.with_label_values(&[tier, result]).inc();
+    }
+
+    pub fn observe_redirect(&self, status:StatusCode, elapsed:Duration){
+        self.redirect_seconds.with_label_values(&[status.as_str()]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn link_created(&self, outcome:&str){
+        self.links_created.with_label_values(&[outcome]).inc();
+    }
+}
+
+pub async fn metrics(Extension(state): Extension<AppState>) -> impl IntoResponse {
+    // pool gauges are sampled at scrape time
+    let m = &*METRICS;
+    let db = state.store.pool_state();
+    m.db_pool.with_label_values(&["idle"]).set(db.idle as i64);
+    m.db_pool.with_label_values(&["in_use"]).set(db.size as i64 - db.idle as i64);
+    m.db_pool.with_label_values(&["max"]).set(db.max as i64);
+    // the in-memory cache has no pool
+    if let Some(redis) = state.cache.shared().pool_state() {
+        m.redis_pool.with_label_values(&["idle"]).set(redis.idle as i64);
+        m.redis_pool.with_label_values(&["in_use"]).set(redis.size as i64 - redis.idle as i64);
+        m.redis_pool.with_label_values(&["max"]).set(redis.max as i64);
+    }
+    let mut buf = Vec::new();
+    match TextEncoder::new().encode(&m.registry.gather(), &mut buf) {
+        Ok(()) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], buf).into_response(),
+        Err(e) => {
+            tracing::error!("failed to encode metrics: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Span for one request: method, the matched route, and the short code when the route has one.
+pub fn request_span(req:&Request)->tracing::Span{
+    let route = req.extensions().get::<MatchedPath>().map(MatchedPath::as_str).unwrap_or("");
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        route,
+        code = tracing::field::Empty,
+    );
+    // the {code} segment of the template is the code segment of the path
+    if let Some(pos) = route.split('/').position(|seg| seg == "{code}")
+        && let Some(code) = req.uri().path().split('/').nth(pos) {
+        span.record("code", code);
+    }
+    span
+}
@@ -0,0 +1,33 @@
+/// Page served at `/{code}+`: shows the destination without redirecting or counting a click.
+pub fn render(short_url:&str, target:&str)->String{
+    let short_url = escape_html(short_url);
+    let target = escape_html(target);
+    format!(r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="robots" content="noindex">
+<title>Preview of {short_url}</title>
+</head>
+<body>
+<p><code>{short_url}</code> points to:</p>
+<p><a href="{target}" rel="noopener noreferrer nofollow">{target}</a></p>
+</body>
+</html>
+"#)
+}
+
+pub fn escape_html(raw:&str)->String{
+    let mut out = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
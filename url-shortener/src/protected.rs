@@ -0,0 +1,122 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    extract::{ConnectInfo, Path, RawQuery},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse},
+    Extension, Form,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+use crate::api::{follow_link, lookup_link, AppState};
+use crate::errors::AppError;
+use crate::preview::escape_html;
+
+const MAX_PASSWORD_LEN: usize = 1024;
+
+#[derive(Deserialize)]
+pub struct UnlockForm{
+    pub password: String,
+}
+
+pub fn check_password(password:&str)->Result<(), AppError>{
+    if password.is_empty() || password.len() > MAX_PASSWORD_LEN {
+        return Err(AppError::BadRequest(format!("password must be 1 to {} bytes", MAX_PASSWORD_LEN)));
+    }
+    Ok(())
+}
+
+pub fn check_max_clicks(max_clicks:i64)->Result<(), AppError>{
+    if max_clicks <= 0 {
+        return Err(AppError::BadRequest("max_clicks must be positive".into()));
+    }
+    Ok(())
+}
+
+/// Argon2id hash in PHC string format; runs on the blocking pool since hashing is deliberately slow.
+pub async fn hash_password(password:String)->anyhow::Result<String>{
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow::anyhow!("failed to hash password: {}", e))
+    }).await?
+}
+
+async fn verify_password(hash:String, password:String)->bool{
+    let res = tokio::task::spawn_blocking(move || {
+        let parsed = PasswordHash::new(&hash)?;
+        Argon2::default().verify_password(password.as_bytes(), &parsed)
+    }).await;
+    match res {
+        Ok(Ok(())) => true,
+        Ok(Err(argon2::password_hash::Error::Password)) => false,
+        Ok(Err(e)) => {
+            tracing::error!("stored password hash is unusable: {}", e);
+            false
+        }
+        Err(e) => {
+            tracing::error!("password check panicked: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Password form served at `/{code}` and `/{code}+` for protected links; it always posts to
+/// `/{code}`, keeping the query string for passthrough links.
+pub fn render_form(code:&str, query:Option<&str>, wrong_password:bool)->String{
+    let action = match query {
+        Some(query) => escape_html(&format!("/{}?{}", code, query)),
+        None => escape_html(&format!("/{}", code)),
+    };
+    let error = if wrong_password { "<p role=\"alert\">Wrong password, try again.</p>\n" } else { "" };
+    format!(r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="robots" content="noindex">
+<title>Password required</title>
+</head>
+<body>
+<p>This link is password protected.</p>
+{error}<form method="post" action="{action}">
+<input type="password" name="password" autocomplete="current-password" autofocus required>
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>
+"#)
+}
+
+pub fn form_response(code:&str, query:Option<&str>, status:StatusCode, wrong_password:bool)->axum::response::Response{
+    (status, [(header::CACHE_CONTROL, "no-store")], Html(render_form(code, query, wrong_password))).into_response()
+}
+
+pub async fn unlock(Extension(state): Extension<AppState>,
+ConnectInfo(peer):ConnectInfo<SocketAddr>,
+headers:HeaderMap,
+Path(code):Path<String>,
+RawQuery(query):RawQuery,
+Form(form):Form<UnlockForm>
+) -> impl IntoResponse {
+    let domain = match state.domains.for_request(&headers) {
+        Ok(domain) => domain,
+        Err(e) => return e.into_response(),
+    };
+    let link = match lookup_link(&state, domain.as_ref(), &code).await {
+        Ok(link) => link,
+        Err(e) => return e.into_response(),
+    };
+    if link.password_hash.is_some() && let Err(e) = state.unlock_link_limit.check(&format!("link:{}", link.id)).await {
+        return e.into_response();
+    }
+    if let Some(hash) = link.password_hash.clone() && !verify_password(hash, form.password).await {
+        tracing::info!("wrong password for {}", code);
+        return form_response(&code, query.as_deref(), StatusCode::UNAUTHORIZED, true);
+    }
+    // see other so the browser follows with a GET
+    follow_link(&state, domain.as_ref(), &code, &link, query.as_deref(), peer, &headers, Some(StatusCode::SEE_OTHER)).await
+}
@@ -0,0 +1,83 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::http::HeaderMap;
+
+/// The reverse proxies in front of the api. Forwarding headers are only believed when the
+/// connection comes from one of them, since any client can send `X-Forwarded-For`.
+#[derive(Clone, Default)]
+pub struct TrustedProxies{
+    nets: Arc<Vec<(IpAddr, u8)>>,
+}
+
+impl TrustedProxies {
+    /// Parses addresses and CIDR ranges such as `10.0.0.0/8` or `::1`.
+    pub fn parse(entries:&[String])->Result<Self, String>{
+        let nets = entries.iter().map(|entry| parse_net(entry)).collect::<Result<Vec<_>, _>>()?;
+        Ok(TrustedProxies{nets: Arc::new(nets)})
+    }
+
+    pub fn contains(&self, ip:IpAddr)->bool{
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|(net, len)| in_net(ip, *net, *len))
+    }
+
+    /// The address of the client. Behind trusted proxies that is the right-most
+    /// `X-Forwarded-For` entry that is not one of them: every proxy appends the address it saw,
+    /// so entries left of that were written by the client and prove nothing.
+    pub fn client_ip(&self, peer:SocketAddr, headers:&HeaderMap)->IpAddr{
+        let mut ip = peer.ip().to_canonical();
+        if !self.contains(ip) {
+            return ip;
+        }
+        let hops: Vec<&str> = headers.get_all("x-forwarded-for").iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        for hop in hops.into_iter().rev() {
+            // a garbled entry ends the chain at the last proxy we trust
+            let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            ip = hop.to_canonical();
+            if !self.contains(ip) {
+                break;
+            }
+        }
+        ip
+    }
+
+    /// Whether headers set by the proxy, such as the country header, can be believed.
+    pub fn is_proxy(&self, peer:SocketAddr)->bool{
+        self.contains(peer.ip())
+    }
+}
+
+fn parse_net(entry:&str)->Result<(IpAddr, u8), String>{
+    let invalid = || format!("trusted_proxies contains an invalid address or range {:?}", entry);
+    let (addr, len) = match entry.split_once('/') {
+        Some((addr, len)) => (addr, Some(len.parse::<u8>().map_err(|_| invalid())?)),
+        None => (entry, None),
+    };
+    let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    match len {
+        Some(len) if len > max => Err(invalid()),
+        Some(len) => Ok((addr, len)),
+        None => Ok((addr, max)),
+    }
+}
+
+fn in_net(ip:IpAddr, net:IpAddr, len:u8)->bool{
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::Cursor;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, EcLevel, QrCode};
+use serde::Deserialize;
+
+use crate::api::{lookup_link, short_url, AppState};
+
+const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat{
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Deserialize)]
+pub struct QrParams{
+    #[serde(default)]
+    pub format: QrFormat,
+    /// minimum width and height in pixels
+    pub size: Option<u32>,
+    /// error correction level: L, M, Q or H
+    pub ecc: Option<String>,
+}
+
+pub async fn qr_code(Extension(state): Extension<AppState>, headers: HeaderMap, Path(code): Path<String>, Query(params): Query<QrParams>) -> impl IntoResponse {
+    let ec_level = match params.ecc.as_deref().map(str::to_ascii_uppercase).as_deref() {
+        None | Some("M") => EcLevel::M,
+        Some("L") => EcLevel::L,
+        Some("Q") => EcLevel::Q,
+        Some("H") => EcLevel::H,
+        Some(_) => return (StatusCode::BAD_REQUEST, "ecc must be one of L, M, Q, H").into_response(),
+    };
+    let size = params.size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+    // same existence, deletion and expiry checks as a redirect, without counting a click
+    let domain = match state.domains.for_request(&headers) {
+        Ok(domain) => domain,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = lookup_link(&state, domain.as_ref(), &code).await {
+        return e.into_response();
+    }
+
+    let short_url = short_url(&state.base_url, domain.as_ref().map(|d| d.host.as_str()), &code);
+    let qr = match QrCode::with_error_correction_level(short_url.as_bytes(), ec_level) {
+        Ok(qr) => qr,
+        Err(e) => {
+            tracing::error!("qr encoding failed for {}: {:?}", code, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "qr encoding failed").into_response();
+        }
+    };
+    match params.format {
+        QrFormat::Svg => {
+            let body = qr.render::<svg::Color>().min_dimensions(size, size).build();
+            ([(header::CONTENT_TYPE, "image/svg+xml")], body).into_response()
+        }
+        QrFormat::Png => {
+            let img = qr.render::<Luma<u8>>().min_dimensions(size, size).build();
+            let mut body = Vec::new();
+            if let Err(e) = img.write_to(&mut Cursor::new(&mut body), ImageFormat::Png) {
+                tracing::error!("png encoding failed for {}: {:?}", code, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "png encoding failed").into_response();
+            }
+            ([(header::CONTENT_TYPE, "image/png")], body).into_response()
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    response::{IntoResponse, Response},
+};
+use deadpool_redis::redis::Script;
+use lru::LruCache;
+use tower::{Layer, Service};
+
+use crate::auth::Owner;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::proxy::TrustedProxies;
+
+// token bucket kept in a redis hash so every api instance draws from the same bucket;
+// uses the redis clock so instances with skewed clocks still agree
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| Script::new(r#"
+local rate = tonumber(ARGV[1])
+local capacity = tonumber(ARGV[2])
+local requested = tonumber(ARGV[3])
+local t = redis.call('TIME')
+local now = tonumber(t[1]) * 1000 + math.floor(tonumber(t[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) / 1000 * rate)
+local retry_ms = 0
+if tokens >= requested then
+  tokens = tokens - requested
+else
+  retry_ms = math.ceil((requested - tokens) / rate * 1000)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate * 1000) + 1000)
+return retry_ms
+"#));
+
+#[derive(Clone, Debug)]
+pub struct BucketConfig{
+    /// tokens added per second
+    pub rate: f64,
+    pub capacity: f64,
+}
+
+impl BucketConfig {
+    pub fn from_config(config:&Config)->Self{
+        BucketConfig{rate: config.rate_limit_per_minute / 60.0, capacity: config.rate_limit_burst}
+    }
+
+    /// Password guesses per protected link, whichever addresses they come from.
+    pub fn for_unlock(config:&Config)->Self{
+        BucketConfig{rate: config.unlock_per_link_per_minute / 60.0, capacity: config.unlock_per_link_burst}
+    }
+}
+
+/// Limits requests per API key, or per client IP when the request carries no key.
+#[derive(Clone)]
+pub struct RateLimitLayer{
+    inner: Arc<Limiter>,
+}
+
+// buckets kept per process when there is no redis; the least recently used go first
+const LOCAL_BUCKETS: NonZeroUsize = NonZeroUsize::new(10_000).unwrap();
+
+struct Limiter{
+    /// none keeps the buckets in `local` instead
+    redis_pool: Option<deadpool_redis::Pool>,
+    local: Mutex<LruCache<String, (f64, Instant)>>,
+    config: BucketConfig,
+    trusted_proxies: TrustedProxies,
+    // keeps buckets of different routes apart
+    scope: &'static str,
+}
+
+impl RateLimitLayer {
+    pub fn new(redis_pool:Option<deadpool_redis::Pool>, config:BucketConfig, trusted_proxies:TrustedProxies, scope:&'static str)->Self{
+        let local = Mutex::new(LruCache::new(LOCAL_BUCKETS));
+        RateLimitLayer{inner: Arc::new(Limiter{redis_pool, local, config, trusted_proxies, scope})}
+    }
+
+    /// Takes a token from the bucket of `name` within the scope, for limits on something other
+    /// than the caller.
+    pub async fn check(&self, name:&str)->Result<(), AppError>{
+        self.inner.check(&format!("ratelimit:{}:{}", self.inner.scope, name)).await
+    }
+
+    /// Takes a token from the bucket the layer uses for requests with `owner`'s API key, for
+    /// requests that do the work of several.
+    pub async fn check_owner(&self, owner:&Owner)->Result<(), AppError>{
+        self.inner.check(&self.inner.owner_key(owner)).await
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit{inner, limiter: self.inner.clone()}
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S>{
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // the ready service goes into the future, a fresh clone stays behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let key = limiter.bucket_key(&req);
+            match limiter.check(&key).await {
+                Ok(()) => inner.call(req).await,
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+impl Limiter {
+    async fn check(&self, key:&str)->Result<(), AppError>{
+        match self.acquire(key).await {
+            Ok(0) => Ok(()),
+            Ok(retry_ms) => {
+                tracing::info!("rate limited {}", key);
+                Err(AppError::RateLimited{retry_after_secs: retry_ms.div_ceil(1000).max(1)})
+            }
+            Err(e) => {
+                // fail open, a redis outage should not block link creation
+                tracing::warn!("rate limiter unavailable: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    fn bucket_key(&self, req:&Request)->String{
+        if let Some(owner) = req.extensions().get::<Owner>() {
+            return self.owner_key(owner);
+        }
+        let peer = req.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr)
+            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        format!("ratelimit:{}:ip:{}", self.scope, self.trusted_proxies.client_ip(peer, req.headers()))
+    }
+
+    fn owner_key(&self, owner:&Owner)->String{
+        format!("ratelimit:{}:key:{}", self.scope, owner.key_id)
+    }
+
+    /// Returns 0 when a token was taken, otherwise the milliseconds until one is available.
+    async fn acquire(&self, key:&str)->anyhow::Result<u64>{
+        let Some(pool) = &self.redis_pool else {
+            return Ok(self.acquire_local(key));
+        };
+        let mut conn = pool.get().await?;
+        let retry_ms: u64 = TOKEN_BUCKET
+            .key(key)
+            .arg(self.config.rate)
+            .arg(self.config.capacity)
+            .arg(1)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(retry_ms)
+    }
+
+    // same bucket as the redis script, on this process's clock
+    fn acquire_local(&self, key:&str)->u64{
+        let BucketConfig{rate, capacity} = self.config;
+        let now = Instant::now();
+        let mut buckets = self.local.lock().expect("rate limit lock poisoned");
+        let (tokens, ts) = buckets.get(key).copied().unwrap_or((capacity, now));
+        let mut tokens = (tokens + now.duration_since(ts).as_secs_f64() * rate).min(capacity);
+        let retry_ms = if tokens >= 1.0 {
+            tokens -= 1.0;
+            0
+        } else {
+            ((1.0 - tokens) / rate * 1000.0).ceil() as u64
+        };
+        buckets.put(key.to_string(), (tokens, now));
+        retry_ms
+    }
+}
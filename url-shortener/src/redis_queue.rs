@@ -1,8 +1,90 @@
-use deadpool_redis::{Config,Pool};
-use redis::AsyncCommands;
+use std::time::Duration;
 
-pub async fn create_pool(redis_url:&str)->anyhow::Result<Pool>{
-    let cfg = Config::from_url(redis_url);
+use deadpool_redis::{Config,Pool,PoolConfig,Timeouts};
+use deadpool_redis::redis::{self, AsyncTypedCommands};
+use serde::{Deserialize, Serialize};
+
+pub const CLICK_QUEUE: &str = "queue:clicks";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClickEvent{
+    pub url_id: i64,
+    pub clicked_at: chrono::DateTime<chrono::Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    /// the link target the click was sent to, none for the link's own url
+    #[serde(default)]
+    pub target_id: Option<i64>,
+}
+
+// every redirect cache entry lives under this prefix
+pub const LINK_CACHE_PREFIX: &str = "short:";
+
+/// short:{code} on base_url, short:{host}/{code} on a custom domain.
+pub fn link_cache_key(domain:Option<&str>, code:&str)->String{
+    match domain {
+        Some(host) => format!("{}{}/{}", LINK_CACHE_PREFIX, host, code),
+        None => format!("{}{}", LINK_CACHE_PREFIX, code),
+    }
+}
+
+// keeps a dead redis from stalling requests that only use it as a cache
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub async fn create_pool(redis_url:&str, pool_size:usize)->anyhow::Result<Pool>{
+    let mut cfg = Config::from_url(redis_url);
+    cfg.pool = Some(PoolConfig{
+        timeouts: Timeouts{
+            wait: Some(REDIS_TIMEOUT),
+            create: Some(REDIS_TIMEOUT),
+            recycle: Some(REDIS_TIMEOUT),
+        },
+        ..PoolConfig::new(pool_size)
+    });
     let pool=cfg.create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
     Ok(pool)
-}
\ No newline at end of file
+}
+
+pub async fn push_click(pool:&Pool, event:&ClickEvent)->anyhow::Result<()>{
+    let payload = serde_json::to_string(event)?;
+    let mut conn = pool.get().await?;
+    conn.lpush(CLICK_QUEUE, payload).await?;
+    Ok(())
+}
+
+/// Blocks for up to `wait_secs` for the first event, then drains up to `max` events without waiting.
+pub async fn pop_clicks(pool:&Pool, max:usize, wait_secs:f64)->anyhow::Result<Vec<ClickEvent>>{
+    let mut conn = pool.get().await?;
+    let Some([_, first]) = conn.brpop(CLICK_QUEUE, wait_secs).await? else {
+        return Ok(Vec::new());
+    };
+    let mut raw = vec![first];
+    if max > 1 {
+        let rest: Option<Vec<String>> = redis::cmd("RPOP")
+            .arg(CLICK_QUEUE)
+            .arg(max - 1)
+            .query_async(&mut conn)
+            .await?;
+        raw.extend(rest.unwrap_or_default());
+    }
+    let events = raw.iter().filter_map(|r| match serde_json::from_str(r){
+        Ok(ev)=>Some(ev),
+        Err(e)=>{
+            tracing::warn!("dropping malformed click event: {:?}", e);
+            None
+        }
+    }).collect();
+    Ok(events)
+}
+
+/// Puts events back at the consuming end of the queue so they are retried first.
+pub async fn requeue_clicks(pool:&Pool, events:&[ClickEvent])->anyhow::Result<()>{
+    if events.is_empty() {
+        return Ok(());
+    }
+    let payloads = events.iter().map(serde_json::to_string).collect::<Result<Vec<_>,_>>()?;
+    let mut conn = pool.get().await?;
+    conn.rpush(CLICK_QUEUE, payloads).await?;
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+/// Exponential backoff between retries of a failing dependency, capped at `max`.
+pub struct Backoff{
+    initial: Duration,
+    current: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial:Duration, max:Duration)->Self{
+        Backoff{initial, current: initial, max}
+    }
+
+    /// The delay to wait now; each call doubles the next one.
+    pub fn next_delay(&mut self)->Duration{
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self){
+        self.current = self.initial;
+    }
+}
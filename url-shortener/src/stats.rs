@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Path, Query},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::api::AppState;
+use crate::auth::Owner;
+use crate::domains::DomainParam;
+use crate::errors::AppError;
+use crate::store::{LinkStore, StoreResult};
+
+const DEFAULT_DAYS: i32 = 30;
+const MAX_DAYS: i32 = 365;
+const TOP_LIMIT: i64 = 10;
+
+#[derive(Deserialize)]
+pub struct StatsParams{
+    pub days: Option<i32>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct LinkInfo{
+    pub(crate) code: String,
+    pub(crate) original_url: String,
+    pub(crate) created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) is_deleted: bool,
+    pub(crate) clicks: i64,
+    pub(crate) password_protected: bool,
+    pub(crate) max_clicks: Option<i64>,
+    /// redirects counted against max_clicks
+    pub(crate) uses: i64,
+    pub(crate) query_passthrough: bool,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct DailyClicks{
+    pub(crate) day: chrono::NaiveDate,
+    pub(crate) clicks: i64,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct TopEntry{
+    pub(crate) value: String,
+    pub(crate) clicks: i64,
+}
+
+/// Clicks per a/b or targeting destination; replaced targets stay listed as inactive.
+#[derive(Serialize, FromRow)]
+pub struct TargetClicks{
+    pub(crate) target_id: i64,
+    pub(crate) url: String,
+    pub(crate) active: bool,
+    pub(crate) clicks: i64,
+}
+
+#[derive(Serialize)]
+pub struct LinkStats{
+    #[serde(flatten)]
+    info: LinkInfo,
+    days: i32,
+    daily: Vec<DailyClicks>,
+    top_referers: Vec<TopEntry>,
+    top_user_agents: Vec<TopEntry>,
+    targets: Vec<TargetClicks>,
+}
+
+pub async fn info(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(code): Path<String>, Query(at): Query<DomainParam>) -> Result<Json<LinkInfo>, AppError> {
+    let domain = state.domains.owned(Some(owner.id), at.domain.as_deref())?;
+    let (_, info) = state.store.link_info(Some(owner.id), domain.map(|d| d.id), &code)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(info))
+}
+
+pub async fn stats(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(code): Path<String>, Query(params): Query<StatsParams>, Query(at): Query<DomainParam>) -> Result<Json<LinkStats>, AppError> {
+    let domain = state.domains.owned(Some(owner.id), at.domain.as_deref())?;
+    let stats = link_stats(state.store.as_ref(), Some(owner.id), domain.map(|d| d.id), &code, params.days)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(stats))
+}
+
+/// Info and click breakdown of a link over the last `days` days; no owner matches any.
+pub async fn link_stats(store:&dyn LinkStore, owner_id:Option<i64>, domain_id:Option<i64>, code:&str, days:Option<i32>)->StoreResult<Option<LinkStats>>{
+    let days = days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let Some((url_id, info)) = store.link_info(owner_id, domain_id, code).await? else {
+        return Ok(None);
+    };
+    let since = chrono::Utc::now() - chrono::Duration::days(days as i64);
+    let b = store.click_breakdown(url_id, since, TOP_LIMIT).await?;
+    Ok(Some(LinkStats{info, days, daily: b.daily, top_referers: b.top_referers, top_user_agents: b.top_user_agents, targets: b.targets}))
+}
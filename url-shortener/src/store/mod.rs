@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::migrate::MigrateError;
+
+use crate::auth::Owner;
+use crate::config::Config;
+use crate::csv_io::CsvLink;
+use crate::domains::Domain;
+use crate::redis_queue::ClickEvent;
+use crate::stats::{DailyClicks, LinkInfo, TargetClicks, TopEntry};
+use crate::targets::LinkTarget;
+use crate::webhooks::{DeadLetter, Delivery, NewDelivery, Subscription, Webhook};
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PgStore;
+pub use sqlite::SqliteStore;
+
+/// Store errors stay `sqlx::Error` whatever the backend, so `db::is_unavailable` and the
+/// `AppError::Db` mapping work unchanged.
+pub type StoreResult<T> = Result<T, sqlx::Error>;
+
+/// A link as the redirect path needs it, whatever its state.
+#[derive(sqlx::FromRow)]
+pub struct StoredLink{
+    pub id: i64,
+    pub original_url: String,
+    pub is_deleted: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redirect_status: i16,
+    pub password_hash: Option<String>,
+    pub max_clicks: Option<i64>,
+    pub uses: i64,
+    pub query_passthrough: bool,
+}
+
+/// A live, unprotected link an owner already has for a url.
+#[derive(sqlx::FromRow)]
+pub struct ExistingLink{
+    pub code: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The columns of a new urls row, with the password already hashed.
+pub struct NewRow<'a>{
+    /// none for base_url
+    pub domain_id: Option<i64>,
+    pub url: &'a str,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redirect_status: Option<i16>,
+    pub password_hash: Option<&'a str>,
+    pub max_clicks: Option<i64>,
+    pub query_passthrough: bool,
+}
+
+/// A partial update of a link; `None` leaves a field alone, `Some(None)` clears it.
+#[derive(Default)]
+pub struct LinkUpdate{
+    pub url: Option<String>,
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    pub redirect_status: Option<i16>,
+    pub password_hash: Option<Option<String>>,
+    pub max_clicks: Option<Option<i64>>,
+    pub query_passthrough: Option<bool>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct UpdatedLink{
+    pub code: String,
+    pub original_url: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redirect_status: i16,
+    pub password_protected: bool,
+    pub max_clicks: Option<i64>,
+    pub uses: i64,
+    pub query_passthrough: bool,
+}
+
+pub struct ListFilter{
+    /// created_at and id of the last link of the previous page
+    pub after: Option<(DateTime<Utc>, i64)>,
+    pub deleted: bool,
+    pub expired: Option<bool>,
+    /// LIKE pattern with `\` as the escape character, matched case-insensitively
+    pub pattern: Option<String>,
+    pub limit: i64,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ListedLink{
+    pub id: i64,
+    pub domain: Option<String>,
+    pub code: String,
+    pub original_url: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub is_deleted: bool,
+    /// past its expiry or out of uses
+    pub expired: bool,
+    pub clicks: i64,
+    pub redirect_status: i16,
+    pub password_protected: bool,
+    pub max_clicks: Option<i64>,
+}
+
+pub struct ClickBreakdown{
+    pub daily: Vec<DailyClicks>,
+    pub top_referers: Vec<TopEntry>,
+    pub top_user_agents: Vec<TopEntry>,
+    pub targets: Vec<TargetClicks>,
+}
+
+/// The link a webhook event is about.
+#[derive(sqlx::FromRow)]
+pub struct LinkRef{
+    pub owner_id: Option<i64>,
+    /// host of the link's custom domain
+    pub domain: Option<String>,
+    pub code: String,
+    pub original_url: String,
+}
+
+/// A link's click count after a batch of clicks was recorded.
+pub struct ClickTotal{
+    pub link: LinkRef,
+    pub clicks: i64,
+    /// how many of `clicks` the batch added
+    pub added: i64,
+}
+
+/// Connection pool occupancy, sampled for the metrics endpoint.
+pub struct PoolState{
+    pub idle: usize,
+    pub size: usize,
+    pub max: usize,
+}
+
+/// Everything the api, the worker and the cli read from or write to the database. Links are
+/// addressed by domain and code; a `domain_id` of `None` is base_url.
+#[async_trait]
+pub trait LinkStore: Send + Sync {
+    /// "postgres" or "sqlite"
+    fn backend(&self)->&'static str;
+    fn pool_state(&self)->PoolState;
+    /// Applies the backend's pending migrations.
+    async fn migrate(&self)->Result<(), MigrateError>;
+    async fn ping(&self)->StoreResult<()>;
+    async fn begin(&self)->StoreResult<Box<dyn LinkTx>>;
+
+    async fn api_key_owner(&self, key_hash:&str)->StoreResult<Option<Owner>>;
+    /// Creates the owner if needed and stores the hash of a new key.
+    async fn create_api_key(&self, owner_name:&str, key_hash:&str)->StoreResult<()>;
+
+    async fn find_link(&self, domain_id:Option<i64>, code:&str)->StoreResult<Option<StoredLink>>;
+    async fn link_targets(&self, url_id:i64)->StoreResult<Vec<LinkTarget>>;
+    /// Takes one use of a click-limited link atomically; false when none are left.
+    async fn take_use(&self, url_id:i64)->StoreResult<bool>;
+    /// One total per link the events were for.
+    async fn record_clicks(&self, events:&[ClickEvent])->StoreResult<Vec<ClickTotal>>;
+
+    /// `None` when the owner has no such live link; no owner matches any, for the admin cli.
+    async fn delete_link(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<LinkRef>>;
+    async fn update_link(&self, owner_id:i64, domain_id:Option<i64>, code:&str, update:&LinkUpdate)->StoreResult<Option<UpdatedLink>>;
+    /// Soft-deletes the current targets and inserts `targets`; `None` when the owner has no such live link.
+    async fn replace_targets(&self, owner_id:i64, domain_id:Option<i64>, code:&str, targets:&[LinkTarget])->StoreResult<Option<Vec<LinkTarget>>>;
+    /// Newest first.
+    async fn list_links(&self, owner_id:i64, filter:&ListFilter)->StoreResult<Vec<ListedLink>>;
+    /// No owner matches any, for the admin cli.
+    async fn link_info(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<(i64, LinkInfo)>>;
+    async fn click_breakdown(&self, url_id:i64, since:DateTime<Utc>, top:i64)->StoreResult<ClickBreakdown>;
+
+    /// Marks links past their expiry as swept and returns the ones not swept before. They are
+    /// not deleted, so they still list and answer as expired.
+    async fn sweep_expired(&self)->StoreResult<Vec<LinkRef>>;
+    /// Every link, deleted ones included, in creation order.
+    async fn export_links(&self)->StoreResult<Vec<CsvLink>>;
+
+    /// Creates the owner if needed; `None` when the host is taken.
+    async fn create_domain(&self, owner_name:&str, host:&str)->StoreResult<Option<Domain>>;
+    /// All domains, or one owner's.
+    async fn list_domains(&self, owner_id:Option<i64>)->StoreResult<Vec<Domain>>;
+    /// False when there is no such domain or links were created on it.
+    async fn delete_domain(&self, host:&str)->StoreResult<bool>;
+
+    async fn create_webhook(&self, owner_id:i64, url:&str, secret:&str, events:&[String], click_thresholds:&[i64])->StoreResult<Webhook>;
+    async fn list_webhooks(&self, owner_id:i64)->StoreResult<Vec<Webhook>>;
+    /// Soft-deletes the webhook and drops its pending deliveries; false when the owner has no such webhook.
+    async fn delete_webhook(&self, owner_id:i64, id:i64)->StoreResult<bool>;
+    /// Live webhooks of these owners that subscribe to `event`.
+    async fn subscriptions(&self, owner_ids:&[i64], event:&str)->StoreResult<Vec<Subscription>>;
+    async fn enqueue_deliveries(&self, deliveries:&[NewDelivery])->StoreResult<()>;
+    /// Takes up to `limit` due deliveries, counting an attempt on each, and holds them for
+    /// `lease` so no other worker picks them up meanwhile.
+    async fn claim_deliveries(&self, limit:i64, lease:std::time::Duration)->StoreResult<Vec<Delivery>>;
+    async fn delivery_succeeded(&self, id:i64)->StoreResult<()>;
+    async fn delivery_failed(&self, id:i64, error:&str, retry_at:DateTime<Utc>)->StoreResult<()>;
+    /// Moves a delivery that used up its attempts to the dead-letter table.
+    async fn dead_letter(&self, id:i64, error:&str)->StoreResult<()>;
+    /// Newest first, optionally only those of one webhook.
+    async fn dead_letters(&self, owner_id:i64, webhook_id:Option<i64>, limit:i64)->StoreResult<Vec<DeadLetter>>;
+}
+
+/// Writes that have to commit together: link creation, bulk creation and csv import.
+#[async_trait]
+pub trait LinkTx: Send {
+    async fn find_reusable(&mut self, owner_id:i64, domain_id:Option<i64>, url:&str)->StoreResult<Option<ExistingLink>>;
+    /// Next value of the code sequence, for the sequence code strategy.
+    async fn next_sequence(&mut self)->StoreResult<i64>;
+    /// False when the code is taken on the row's domain.
+    async fn insert_link(&mut self, owner_id:i64, code:&str, row:&NewRow<'_>)->StoreResult<bool>;
+    /// Id of the named owner, created if needed.
+    async fn owner_id(&mut self, name:&str)->StoreResult<i64>;
+    async fn domain(&mut self, host:&str)->StoreResult<Option<Domain>>;
+    /// Inserts a link as exported, keeping its code; false when the code is taken.
+    async fn import_link(&mut self, owner_id:Option<i64>, domain_id:Option<i64>, link:&CsvLink)->StoreResult<bool>;
+    async fn commit(self:Box<Self>)->StoreResult<()>;
+}
+
+/// Opens the store `database_url` points at: `postgres://` or `sqlite:`.
+pub async fn connect(config:&Config)->anyhow::Result<Arc<dyn LinkStore>>{
+    if is_sqlite_url(&config.database_url) {
+        Ok(Arc::new(SqliteStore::connect(&config.database_url)?))
+    } else {
+        Ok(Arc::new(PgStore::connect(&config.database_url, config.db_max_connections)?))
+    }
+}
+
+pub fn is_sqlite_url(url:&str)->bool{
+    url.starts_with("sqlite:")
+}
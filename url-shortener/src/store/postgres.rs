@@ -0,0 +1,631 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::auth::Owner;
+use crate::csv_io::CsvLink;
+use crate::domains::Domain;
+use crate::redis_queue::ClickEvent;
+use crate::stats::{DailyClicks, LinkInfo, TargetClicks, TopEntry};
+use crate::targets::LinkTarget;
+use crate::webhooks::{DeadLetter, Delivery, NewDelivery, Subscription, Webhook};
+
+use super::{ClickBreakdown, ClickTotal, LinkRef, ExistingLink, LinkStore, LinkTx, LinkUpdate, ListFilter, ListedLink, NewRow, PoolState, StoreResult, StoredLink, UpdatedLink};
+
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// everything under migrations/, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub struct PgStore{
+    pool: PgPool,
+}
+
+impl PgStore {
+    /// Connections are opened on first use, so the process starts even while postgres is down.
+    pub fn connect(database_url:&str, max_connections:u32)->anyhow::Result<Self>{
+        let pool = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .acquire_timeout(ACQUIRE_TIMEOUT)
+        .connect_lazy(database_url)?;
+        Ok(PgStore{pool})
+    }
+}
+
+#[async_trait]
+impl LinkStore for PgStore {
+    fn backend(&self)->&'static str{
+        "postgres"
+    }
+
+    fn pool_state(&self)->PoolState{
+        PoolState{
+            idle: self.pool.num_idle(),
+            size: self.pool.size() as usize,
+            max: self.pool.options().get_max_connections() as usize,
+        }
+    }
+
+    async fn migrate(&self)->Result<(), MigrateError>{
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self)->StoreResult<()>{
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn begin(&self)->StoreResult<Box<dyn LinkTx>>{
+        Ok(Box::new(PgTx{tx: self.pool.begin().await?}))
+    }
+
+    async fn api_key_owner(&self, key_hash:&str)->StoreResult<Option<Owner>>{
+        let key = sqlx::query!(
+            r#"SELECT id, owner_id FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL"#,
+            key_hash
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(key.map(|key| Owner{id: key.owner_id, key_id: key.id}))
+    }
+
+    async fn create_api_key(&self, owner_name:&str, key_hash:&str)->StoreResult<()>{
+        let mut tx = self.pool.begin().await?;
+        let owner_id = upsert_owner(&mut tx, owner_name).await?;
+        sqlx::query!(
+            r#"INSERT INTO api_keys (owner_id, key_hash) VALUES ($1, $2)"#,
+            owner_id,
+            key_hash
+        )
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    async fn find_link(&self, domain_id:Option<i64>, code:&str)->StoreResult<Option<StoredLink>>{
+        sqlx::query_as!(
+            StoredLink,
+            r#"SELECT id, original_url, COALESCE(is_deleted, FALSE) AS "is_deleted!", expires_at, redirect_status,
+                      password_hash, max_clicks, uses, query_passthrough
+               FROM urls WHERE COALESCE(domain_id, 0) = COALESCE($1::bigint, 0) AND short_code = $2"#,
+            domain_id,
+            code
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn link_targets(&self, url_id:i64)->StoreResult<Vec<LinkTarget>>{
+        sqlx::query_as!(
+            LinkTarget,
+            r#"SELECT id, target_url AS url, weight, country, device
+               FROM link_targets WHERE url_id = $1 AND NOT is_deleted ORDER BY id"#,
+            url_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn take_use(&self, url_id:i64)->StoreResult<bool>{
+        // the conditional update is atomic per row, so concurrent redirects never overdraw
+        let taken = sqlx::query_scalar!(
+            r#"UPDATE urls SET uses = uses + 1 WHERE id = $1 AND uses < max_clicks RETURNING uses"#,
+            url_id
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(taken.is_some())
+    }
+
+    async fn record_clicks(&self, events:&[ClickEvent])->StoreResult<Vec<ClickTotal>>{
+        let url_ids: Vec<i64> = events.iter().map(|e| e.url_id).collect();
+        let clicked_at: Vec<DateTime<Utc>> = events.iter().map(|e| e.clicked_at).collect();
+        let ips: Vec<Option<String>> = events.iter().map(|e| e.ip.clone()).collect();
+        let user_agents: Vec<Option<String>> = events.iter().map(|e| e.user_agent.clone()).collect();
+        let referers: Vec<Option<String>> = events.iter().map(|e| e.referer.clone()).collect();
+        let target_ids: Vec<Option<i64>> = events.iter().map(|e| e.target_id).collect();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO clicks (url_id, created_at, ip, user_agent, referer, target_id)
+               SELECT c.url_id, c.created_at, c.ip::inet, c.user_agent, c.referer, c.target_id
+               FROM UNNEST($1::bigint[], $2::timestamptz[], $3::text[], $4::text[], $5::text[], $6::bigint[])
+                   AS c(url_id, created_at, ip, user_agent, referer, target_id)"#,
+            &url_ids,
+            &clicked_at,
+            &ips as &[Option<String>],
+            &user_agents as &[Option<String>],
+            &referers as &[Option<String>],
+            &target_ids as &[Option<i64>],
+        ).execute(&mut *tx).await?;
+        let totals = sqlx::query!(
+            r#"UPDATE urls SET clicks = COALESCE(urls.clicks, 0) + c.n
+               FROM (SELECT url_id, count(*) AS n FROM UNNEST($1::bigint[]) AS url_id GROUP BY url_id) c
+               WHERE urls.id = c.url_id
+               RETURNING urls.owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain,
+                         urls.short_code, urls.original_url, urls.clicks AS "clicks!", c.n AS "added!""#,
+            &url_ids,
+        ).fetch_all(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(totals.into_iter().map(|t| ClickTotal{
+            link: LinkRef{owner_id: t.owner_id, domain: t.domain, code: t.short_code, original_url: t.original_url},
+            clicks: t.clicks,
+            added: t.added,
+        }).collect())
+    }
+
+    async fn delete_link(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<LinkRef>>{
+        sqlx::query_as!(
+            LinkRef,
+            r#"UPDATE urls SET is_deleted = TRUE
+               WHERE COALESCE(domain_id, 0) = COALESCE($3::bigint, 0) AND short_code = $1
+                 AND ($2::bigint IS NULL OR owner_id = $2) AND NOT COALESCE(is_deleted, FALSE)
+               RETURNING owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url"#,
+            code,
+            owner_id,
+            domain_id
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_link(&self, owner_id:i64, domain_id:Option<i64>, code:&str, update:&LinkUpdate)->StoreResult<Option<UpdatedLink>>{
+        sqlx::query_as!(
+            UpdatedLink,
+            r#"UPDATE urls
+               SET original_url = COALESCE($2, original_url),
+                   expires_at = CASE WHEN $3 THEN $4 ELSE expires_at END,
+                   redirect_status = COALESCE($6, redirect_status),
+                   password_hash = CASE WHEN $7 THEN $8 ELSE password_hash END,
+                   max_clicks = CASE WHEN $9 THEN $10 ELSE max_clicks END,
+                   query_passthrough = COALESCE($12, query_passthrough),
+                   -- a new expiry is swept and announced again once it passes
+                   expiry_swept = expiry_swept AND NOT $3
+               WHERE COALESCE(domain_id, 0) = COALESCE($11::bigint, 0) AND short_code = $1 AND owner_id = $5 AND NOT COALESCE(is_deleted, FALSE)
+               RETURNING short_code AS code, original_url, expires_at, redirect_status,
+                         password_hash IS NOT NULL AS "password_protected!", max_clicks, uses, query_passthrough"#,
+            code,
+            update.url,
+            update.expires_at.is_some(),
+            update.expires_at.flatten(),
+            owner_id,
+            update.redirect_status,
+            update.password_hash.is_some(),
+            update.password_hash.clone().flatten(),
+            update.max_clicks.is_some(),
+            update.max_clicks.flatten(),
+            domain_id,
+            update.query_passthrough
+        )
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn replace_targets(&self, owner_id:i64, domain_id:Option<i64>, code:&str, targets:&[LinkTarget])->StoreResult<Option<Vec<LinkTarget>>>{
+        let mut tx = self.pool.begin().await?;
+        let Some(url_id) = sqlx::query_scalar!(
+            r#"SELECT id FROM urls
+               WHERE COALESCE(domain_id, 0) = COALESCE($3::bigint, 0) AND short_code = $1 AND owner_id = $2 AND NOT COALESCE(is_deleted, FALSE)
+               FOR UPDATE"#,
+            code,
+            owner_id,
+            domain_id
+        )
+            .fetch_optional(&mut *tx)
+            .await? else {
+            return Ok(None);
+        };
+        // old targets stay behind, soft-deleted, for the click stats
+        sqlx::query!(r#"UPDATE link_targets SET is_deleted = TRUE WHERE url_id = $1 AND NOT is_deleted"#, url_id)
+            .execute(&mut *tx)
+            .await?;
+        let mut saved = Vec::with_capacity(targets.len());
+        for t in targets {
+            let id = sqlx::query_scalar!(
+                r#"INSERT INTO link_targets (url_id, target_url, weight, country, device) VALUES ($1, $2, $3, $4, $5) RETURNING id"#,
+                url_id,
+                t.url,
+                t.weight,
+                t.country,
+                t.device
+            )
+                .fetch_one(&mut *tx)
+                .await?;
+            saved.push(LinkTarget{id, ..t.clone()});
+        }
+        tx.commit().await?;
+        Ok(Some(saved))
+    }
+
+    async fn list_links(&self, owner_id:i64, filter:&ListFilter)->StoreResult<Vec<ListedLink>>{
+        let (after_created, after_id) = filter.after.unzip();
+        sqlx::query_as!(
+            ListedLink,
+            r#"SELECT id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain,
+                      short_code AS code, original_url, created_at AS "created_at!", expires_at,
+                      COALESCE(is_deleted, FALSE) AS "is_deleted!",
+                      (expires_at IS NOT NULL AND expires_at <= now()) OR (max_clicks IS NOT NULL AND uses >= max_clicks) AS "expired!",
+                      COALESCE(clicks, 0) AS "clicks!", redirect_status,
+                      password_hash IS NOT NULL AS "password_protected!", max_clicks
+               FROM urls
+               WHERE owner_id = $1
+                 AND ($2::timestamptz IS NULL OR (created_at, id) < ($2, $3::bigint))
+                 AND COALESCE(is_deleted, FALSE) = $4
+                 AND ($5::bool IS NULL OR ((expires_at IS NOT NULL AND expires_at <= now()) OR (max_clicks IS NOT NULL AND uses >= max_clicks)) = $5)
+                 AND ($6::text IS NULL OR original_url ILIKE $6)
+               ORDER BY created_at DESC, id DESC
+               LIMIT $7"#,
+            owner_id,
+            after_created,
+            after_id,
+            filter.deleted,
+            filter.expired,
+            filter.pattern,
+            filter.limit
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn link_info(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<(i64, LinkInfo)>>{
+        let row = sqlx::query!(
+            r#"SELECT id, short_code, original_url, created_at, expires_at,
+                      COALESCE(is_deleted, FALSE) AS "is_deleted!", COALESCE(clicks, 0) AS "clicks!",
+                      password_hash IS NOT NULL AS "password_protected!", max_clicks, uses, query_passthrough
+               FROM urls WHERE COALESCE(domain_id, 0) = COALESCE($3::bigint, 0) AND short_code = $1 AND ($2::bigint IS NULL OR owner_id = $2)"#,
+            code,
+            owner_id,
+            domain_id
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| (r.id, LinkInfo{
+            code: r.short_code,
+            original_url: r.original_url,
+            created_at: r.created_at,
+            expires_at: r.expires_at,
+            is_deleted: r.is_deleted,
+            clicks: r.clicks,
+            password_protected: r.password_protected,
+            max_clicks: r.max_clicks,
+            uses: r.uses,
+            query_passthrough: r.query_passthrough,
+        })))
+    }
+
+    async fn click_breakdown(&self, url_id:i64, since:DateTime<Utc>, top:i64)->StoreResult<ClickBreakdown>{
+        let daily = sqlx::query_as!(
+            DailyClicks,
+            r#"SELECT (created_at AT TIME ZONE 'UTC')::date AS "day!", count(*) AS "clicks!"
+               FROM clicks WHERE url_id = $1 AND created_at >= $2
+               GROUP BY 1 ORDER BY 1"#,
+            url_id,
+            since
+        )
+            .fetch_all(&self.pool)
+            .await?;
+        let top_referers = sqlx::query_as!(
+            TopEntry,
+            r#"SELECT referer AS "value!", count(*) AS "clicks!"
+               FROM clicks WHERE url_id = $1 AND created_at >= $2 AND referer IS NOT NULL
+               GROUP BY referer ORDER BY 2 DESC, 1 LIMIT $3"#,
+            url_id,
+            since,
+            top
+        )
+            .fetch_all(&self.pool)
+            .await?;
+        let top_user_agents = sqlx::query_as!(
+            TopEntry,
+            r#"SELECT user_agent AS "value!", count(*) AS "clicks!"
+               FROM clicks WHERE url_id = $1 AND created_at >= $2 AND user_agent IS NOT NULL
+               GROUP BY user_agent ORDER BY 2 DESC, 1 LIMIT $3"#,
+            url_id,
+            since,
+            top
+        )
+            .fetch_all(&self.pool)
+            .await?;
+        // targets without clicks in the window only show while they are live
+        let targets = sqlx::query_as!(
+            TargetClicks,
+            r#"SELECT t.id AS target_id, t.target_url AS url, NOT t.is_deleted AS "active!", count(c.id) AS "clicks!"
+               FROM link_targets t
+               LEFT JOIN clicks c ON c.target_id = t.id AND c.created_at >= $2
+               WHERE t.url_id = $1
+               GROUP BY t.id
+               HAVING NOT t.is_deleted OR count(c.id) > 0
+               ORDER BY t.id"#,
+            url_id,
+            since
+        )
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(ClickBreakdown{daily, top_referers, top_user_agents, targets})
+    }
+
+    async fn sweep_expired(&self)->StoreResult<Vec<LinkRef>>{
+        sqlx::query_as!(
+            LinkRef,
+            r#"UPDATE urls SET expiry_swept = TRUE
+               WHERE expires_at <= now() AND NOT expiry_swept AND NOT COALESCE(is_deleted, FALSE)
+               RETURNING owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url"#
+        ).fetch_all(&self.pool).await
+    }
+
+    async fn export_links(&self)->StoreResult<Vec<CsvLink>>{
+        sqlx::query_as!(
+            CsvLink,
+            r#"SELECT u.short_code, u.original_url, u.created_at, u.expires_at, u.is_deleted, u.clicks, o.name AS "owner?", u.redirect_status AS "redirect_status?",
+                      d.host AS "domain?", u.password_hash, u.max_clicks, u.uses AS "uses?", u.query_passthrough AS "query_passthrough?"
+               FROM urls u LEFT JOIN owners o ON o.id = u.owner_id LEFT JOIN domains d ON d.id = u.domain_id
+               ORDER BY u.id"#
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_domain(&self, owner_name:&str, host:&str)->StoreResult<Option<Domain>>{
+        let mut tx = self.pool.begin().await?;
+        let owner_id = upsert_owner(&mut tx, owner_name).await?;
+        let domain = sqlx::query_as!(
+            Domain,
+            r#"INSERT INTO domains (owner_id, host) VALUES ($1, $2)
+               ON CONFLICT (host) DO NOTHING RETURNING id, owner_id, host, created_at"#,
+            owner_id,
+            host
+        )
+            .fetch_optional(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(domain)
+    }
+
+    async fn list_domains(&self, owner_id:Option<i64>)->StoreResult<Vec<Domain>>{
+        sqlx::query_as!(
+            Domain,
+            r#"SELECT id, owner_id, host, created_at FROM domains WHERE $1::bigint IS NULL OR owner_id = $1 ORDER BY host"#,
+            owner_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_domain(&self, host:&str)->StoreResult<bool>{
+        let done = sqlx::query!(
+            r#"DELETE FROM domains WHERE host = $1 AND NOT EXISTS (SELECT 1 FROM urls WHERE domain_id = domains.id)"#,
+            host
+        )
+            .execute(&self.pool)
+            .await?;
+        Ok(done.rows_affected() > 0)
+    }
+
+    async fn create_webhook(&self, owner_id:i64, url:&str, secret:&str, events:&[String], click_thresholds:&[i64])->StoreResult<Webhook>{
+        sqlx::query_as!(
+            Webhook,
+            r#"INSERT INTO webhooks (owner_id, url, secret, events, click_thresholds) VALUES ($1, $2, $3, $4, $5)
+               RETURNING id, url, events, click_thresholds, created_at"#,
+            owner_id,
+            url,
+            secret,
+            events,
+            click_thresholds
+        )
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn list_webhooks(&self, owner_id:i64)->StoreResult<Vec<Webhook>>{
+        sqlx::query_as!(
+            Webhook,
+            r#"SELECT id, url, events, click_thresholds, created_at FROM webhooks
+               WHERE owner_id = $1 AND NOT is_deleted ORDER BY id"#,
+            owner_id
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_webhook(&self, owner_id:i64, id:i64)->StoreResult<bool>{
+        let mut tx = self.pool.begin().await?;
+        let done = sqlx::query!(
+            r#"UPDATE webhooks SET is_deleted = TRUE WHERE id = $1 AND owner_id = $2 AND NOT is_deleted"#,
+            id,
+            owner_id
+        ).execute(&mut *tx).await?;
+        if done.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(r#"DELETE FROM webhook_deliveries WHERE webhook_id = $1"#, id).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn subscriptions(&self, owner_ids:&[i64], event:&str)->StoreResult<Vec<Subscription>>{
+        sqlx::query_as!(
+            Subscription,
+            r#"SELECT id, owner_id, click_thresholds FROM webhooks
+               WHERE owner_id = ANY($1) AND $2 = ANY(events) AND NOT is_deleted"#,
+            owner_ids,
+            event
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn enqueue_deliveries(&self, deliveries:&[NewDelivery])->StoreResult<()>{
+        let webhook_ids: Vec<i64> = deliveries.iter().map(|d| d.webhook_id).collect();
+        let events: Vec<String> = deliveries.iter().map(|d| d.event.to_string()).collect();
+        let payloads: Vec<serde_json::Value> = deliveries.iter().map(|d| d.payload.clone()).collect();
+        sqlx::query!(
+            r#"INSERT INTO webhook_deliveries (webhook_id, event, payload)
+               SELECT * FROM UNNEST($1::bigint[], $2::text[], $3::jsonb[])"#,
+            &webhook_ids,
+            &events,
+            &payloads
+        ).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn claim_deliveries(&self, limit:i64, lease:Duration)->StoreResult<Vec<Delivery>>{
+        sqlx::query_as!(
+            Delivery,
+            r#"UPDATE webhook_deliveries d
+               SET attempts = d.attempts + 1, next_attempt_at = now() + make_interval(secs => $2)
+               FROM webhooks w
+               WHERE w.id = d.webhook_id AND d.id IN (
+                   SELECT id FROM webhook_deliveries WHERE next_attempt_at <= now()
+                   ORDER BY next_attempt_at LIMIT $1 FOR UPDATE SKIP LOCKED)
+               RETURNING d.id, d.webhook_id, w.url, w.secret, d.event, d.payload, d.attempts"#,
+            limit,
+            lease.as_secs_f64()
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delivery_succeeded(&self, id:i64)->StoreResult<()>{
+        sqlx::query!(r#"DELETE FROM webhook_deliveries WHERE id = $1"#, id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delivery_failed(&self, id:i64, error:&str, retry_at:DateTime<Utc>)->StoreResult<()>{
+        sqlx::query!(
+            r#"UPDATE webhook_deliveries SET last_error = $2, next_attempt_at = $3 WHERE id = $1"#,
+            id,
+            error,
+            retry_at
+        ).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn dead_letter(&self, id:i64, error:&str)->StoreResult<()>{
+        sqlx::query!(
+            r#"WITH failed AS (DELETE FROM webhook_deliveries WHERE id = $1 RETURNING *)
+               INSERT INTO webhook_dead_letters (webhook_id, event, payload, attempts, last_error, created_at)
+               SELECT webhook_id, event, payload, attempts, $2, created_at FROM failed"#,
+            id,
+            error
+        ).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn dead_letters(&self, owner_id:i64, webhook_id:Option<i64>, limit:i64)->StoreResult<Vec<DeadLetter>>{
+        sqlx::query_as!(
+            DeadLetter,
+            r#"SELECT l.id, l.webhook_id, l.event, l.payload, l.attempts, l.last_error, l.created_at, l.failed_at
+               FROM webhook_dead_letters l JOIN webhooks w ON w.id = l.webhook_id
+               WHERE w.owner_id = $1 AND ($2::bigint IS NULL OR l.webhook_id = $2)
+               ORDER BY l.id DESC LIMIT $3"#,
+            owner_id,
+            webhook_id,
+            limit
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+struct PgTx{
+    tx: Transaction<'static, Postgres>,
+}
+
+#[async_trait]
+impl LinkTx for PgTx {
+    async fn find_reusable(&mut self, owner_id:i64, domain_id:Option<i64>, url:&str)->StoreResult<Option<ExistingLink>>{
+        sqlx::query_as!(
+            ExistingLink,
+            r#"SELECT short_code AS code, expires_at FROM urls
+               WHERE original_url = $1 AND owner_id = $2 AND COALESCE(domain_id, 0) = COALESCE($3::bigint, 0)
+                 AND NOT COALESCE(is_deleted, FALSE) AND (expires_at IS NULL OR expires_at > now())
+                 AND password_hash IS NULL AND max_clicks IS NULL AND NOT query_passthrough
+               ORDER BY created_at DESC LIMIT 1"#,
+            url,
+            owner_id,
+            domain_id
+        )
+            .fetch_optional(&mut *self.tx)
+            .await
+    }
+
+    async fn next_sequence(&mut self)->StoreResult<i64>{
+        sqlx::query_scalar!(r#"SELECT nextval('short_code_seq') AS "id!""#)
+            .fetch_one(&mut *self.tx)
+            .await
+    }
+
+    async fn insert_link(&mut self, owner_id:i64, code:&str, row:&NewRow<'_>)->StoreResult<bool>{
+        let inserted = sqlx::query_scalar!(
+            r#"INSERT into urls (short_code, original_url, expires_at, owner_id, redirect_status, password_hash, max_clicks, domain_id, query_passthrough)
+               VALUES ($1,$2,$3,$4,COALESCE($5::smallint,302),$6,$7,$8,$9)
+               ON CONFLICT (COALESCE(domain_id, 0), short_code) DO NOTHING RETURNING id"#,
+            code,
+            row.url,
+            row.expires_at,
+            owner_id,
+            row.redirect_status,
+            row.password_hash,
+            row.max_clicks,
+            row.domain_id,
+            row.query_passthrough
+        ).fetch_optional(&mut *self.tx)
+            .await?;
+        Ok(inserted.is_some())
+    }
+
+    async fn owner_id(&mut self, name:&str)->StoreResult<i64>{
+        upsert_owner(&mut self.tx, name).await
+    }
+
+    async fn domain(&mut self, host:&str)->StoreResult<Option<Domain>>{
+        sqlx::query_as!(Domain, r#"SELECT id, owner_id, host, created_at FROM domains WHERE host = $1"#, host)
+            .fetch_optional(&mut *self.tx)
+            .await
+    }
+
+    async fn import_link(&mut self, owner_id:Option<i64>, domain_id:Option<i64>, link:&CsvLink)->StoreResult<bool>{
+        let inserted = sqlx::query_scalar!(
+            r#"INSERT INTO urls (short_code, original_url, created_at, expires_at, is_deleted, clicks, owner_id, redirect_status, domain_id,
+                                 password_hash, max_clicks, uses, query_passthrough)
+               VALUES ($1, $2, COALESCE($3, now()), $4, COALESCE($5, FALSE), COALESCE($6::bigint, 0), $7, COALESCE($8::smallint, 302), $9,
+                       $10, $11, COALESCE($12::bigint, 0), COALESCE($13, FALSE))
+               ON CONFLICT (COALESCE(domain_id, 0), short_code) DO NOTHING RETURNING id"#,
+            link.short_code,
+            link.original_url,
+            link.created_at,
+            link.expires_at,
+            link.is_deleted,
+            link.clicks,
+            owner_id,
+            link.redirect_status,
+            domain_id,
+            link.password_hash,
+            link.max_clicks,
+            link.uses,
+            link.query_passthrough
+        )
+            .fetch_optional(&mut *self.tx)
+            .await?;
+        Ok(inserted.is_some())
+    }
+
+    async fn commit(self:Box<Self>)->StoreResult<()>{
+        self.tx.commit().await
+    }
+}
+
+async fn upsert_owner(tx:&mut Transaction<'static, Postgres>, name:&str)->StoreResult<i64>{
+    sqlx::query_scalar!(
+        r#"INSERT INTO owners (name) VALUES ($1)
+           ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+           RETURNING id"#,
+        name
+    )
+        .fetch_one(&mut **tx)
+        .await
+}
@@ -0,0 +1,637 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::{FromRow, Sqlite, Transaction};
+
+use crate::auth::Owner;
+use crate::csv_io::CsvLink;
+use crate::domains::Domain;
+use crate::redis_queue::ClickEvent;
+use crate::stats::{DailyClicks, LinkInfo, TargetClicks, TopEntry};
+use crate::targets::LinkTarget;
+use crate::webhooks::{DeadLetter, Delivery, NewDelivery, Subscription, Webhook};
+
+use super::{ClickBreakdown, ClickTotal, LinkRef, ExistingLink, LinkStore, LinkTx, LinkUpdate, ListFilter, ListedLink, NewRow, PoolState, StoreResult, StoredLink, UpdatedLink};
+
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(3);
+// how long a write waits for another process, e.g. the cli, to release the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./sqlite_migrations");
+
+/// Embedded single-file backend for deployments without postgres. The queries are checked at
+/// runtime only, since the query macros are bound to the postgres schema.
+pub struct SqliteStore{
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub fn connect(database_url:&str)->anyhow::Result<Self>{
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(BUSY_TIMEOUT)
+            .foreign_keys(true);
+        // sqlite has one writer anyway; a single long-lived connection also rules out
+        // upgrade deadlocks between transactions and keeps a :memory: database alive
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect_lazy_with(options);
+        Ok(SqliteStore{pool})
+    }
+}
+
+// fixed width, so stored timestamps order correctly as text
+fn ts(at:DateTime<Utc>)->String{
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[derive(FromRow)]
+struct TotalRow{
+    #[sqlx(flatten)]
+    link: LinkRef,
+    clicks: i64,
+}
+
+#[derive(FromRow)]
+struct InfoRow{
+    id: i64,
+    #[sqlx(flatten)]
+    info: LinkInfo,
+}
+
+#[async_trait]
+impl LinkStore for SqliteStore {
+    fn backend(&self)->&'static str{
+        "sqlite"
+    }
+
+    fn pool_state(&self)->PoolState{
+        PoolState{
+            idle: self.pool.num_idle(),
+            size: self.pool.size() as usize,
+            max: self.pool.options().get_max_connections() as usize,
+        }
+    }
+
+    async fn migrate(&self)->Result<(), MigrateError>{
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self)->StoreResult<()>{
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn begin(&self)->StoreResult<Box<dyn LinkTx>>{
+        Ok(Box::new(SqliteTx{tx: self.pool.begin().await?}))
+    }
+
+    async fn api_key_owner(&self, key_hash:&str)->StoreResult<Option<Owner>>{
+        let key: Option<(i64, i64)> = sqlx::query_as(
+            r#"SELECT id, owner_id FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL"#
+        )
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(key.map(|(key_id, id)| Owner{id, key_id}))
+    }
+
+    async fn create_api_key(&self, owner_name:&str, key_hash:&str)->StoreResult<()>{
+        let mut tx = self.pool.begin().await?;
+        let owner_id = upsert_owner(&mut tx, owner_name).await?;
+        sqlx::query(r#"INSERT INTO api_keys (owner_id, key_hash, created_at) VALUES (?, ?, ?)"#)
+            .bind(owner_id)
+            .bind(key_hash)
+            .bind(ts(Utc::now()))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    async fn find_link(&self, domain_id:Option<i64>, code:&str)->StoreResult<Option<StoredLink>>{
+        sqlx::query_as(
+            r#"SELECT id, original_url, is_deleted, expires_at, redirect_status, password_hash, max_clicks, uses, query_passthrough
+               FROM urls WHERE COALESCE(domain_id, 0) = COALESCE(?, 0) AND short_code = ?"#
+        )
+            .bind(domain_id)
+            .bind(code)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn link_targets(&self, url_id:i64)->StoreResult<Vec<LinkTarget>>{
+        sqlx::query_as(
+            r#"SELECT id, target_url AS url, weight, country, device
+               FROM link_targets WHERE url_id = ? AND NOT is_deleted ORDER BY id"#
+        )
+            .bind(url_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn take_use(&self, url_id:i64)->StoreResult<bool>{
+        // a single statement on the single connection, so uses can never overshoot
+        let taken: Option<i64> = sqlx::query_scalar(
+            r#"UPDATE urls SET uses = uses + 1 WHERE id = ? AND uses < max_clicks RETURNING uses"#
+        )
+            .bind(url_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(taken.is_some())
+    }
+
+    async fn record_clicks(&self, events:&[ClickEvent])->StoreResult<Vec<ClickTotal>>{
+        let mut added: BTreeMap<i64, i64> = BTreeMap::new();
+        let mut tx = self.pool.begin().await?;
+        for e in events {
+            sqlx::query(
+                r#"INSERT INTO clicks (url_id, created_at, ip, user_agent, referer, target_id) VALUES (?, ?, ?, ?, ?, ?)"#
+            )
+                .bind(e.url_id)
+                .bind(ts(e.clicked_at))
+                .bind(&e.ip)
+                .bind(&e.user_agent)
+                .bind(&e.referer)
+                .bind(e.target_id)
+                .execute(&mut *tx)
+                .await?;
+            *added.entry(e.url_id).or_default() += 1;
+        }
+        let mut totals = Vec::with_capacity(added.len());
+        for (url_id, n) in added {
+            let row: Option<TotalRow> = sqlx::query_as(
+                r#"UPDATE urls SET clicks = clicks + ?2 WHERE id = ?1
+                   RETURNING owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url, clicks"#
+            )
+                .bind(url_id)
+                .bind(n)
+                .fetch_optional(&mut *tx)
+                .await?;
+            totals.extend(row.map(|r| ClickTotal{link: r.link, clicks: r.clicks, added: n}));
+        }
+        tx.commit().await?;
+        Ok(totals)
+    }
+
+    async fn delete_link(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<LinkRef>>{
+        sqlx::query_as(
+            r#"UPDATE urls SET is_deleted = TRUE
+               WHERE COALESCE(domain_id, 0) = COALESCE(?3, 0) AND short_code = ?1 AND (?2 IS NULL OR owner_id = ?2) AND NOT is_deleted
+               RETURNING owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url"#
+        )
+            .bind(code)
+            .bind(owner_id)
+            .bind(domain_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_link(&self, owner_id:i64, domain_id:Option<i64>, code:&str, update:&LinkUpdate)->StoreResult<Option<UpdatedLink>>{
+        sqlx::query_as(
+            r#"UPDATE urls
+               SET original_url = COALESCE(?1, original_url),
+                   expires_at = CASE WHEN ?2 THEN ?3 ELSE expires_at END,
+                   redirect_status = COALESCE(?5, redirect_status),
+                   password_hash = CASE WHEN ?6 THEN ?7 ELSE password_hash END,
+                   max_clicks = CASE WHEN ?8 THEN ?9 ELSE max_clicks END,
+                   query_passthrough = COALESCE(?12, query_passthrough),
+                   -- a new expiry is swept and announced again once it passes
+                   expiry_swept = expiry_swept AND NOT ?2
+               WHERE COALESCE(domain_id, 0) = COALESCE(?11, 0) AND short_code = ?10 AND owner_id = ?4 AND NOT is_deleted
+               RETURNING short_code AS code, original_url, expires_at, redirect_status,
+                         password_hash IS NOT NULL AS password_protected, max_clicks, uses, query_passthrough"#
+        )
+            .bind(&update.url)
+            .bind(update.expires_at.is_some())
+            .bind(update.expires_at.flatten().map(ts))
+            .bind(owner_id)
+            .bind(update.redirect_status)
+            .bind(update.password_hash.is_some())
+            .bind(update.password_hash.clone().flatten())
+            .bind(update.max_clicks.is_some())
+            .bind(update.max_clicks.flatten())
+            .bind(code)
+            .bind(domain_id)
+            .bind(update.query_passthrough)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn replace_targets(&self, owner_id:i64, domain_id:Option<i64>, code:&str, targets:&[LinkTarget])->StoreResult<Option<Vec<LinkTarget>>>{
+        let mut tx = self.pool.begin().await?;
+        let url_id: Option<i64> = sqlx::query_scalar(
+            r#"SELECT id FROM urls WHERE COALESCE(domain_id, 0) = COALESCE(?, 0) AND short_code = ? AND owner_id = ? AND NOT is_deleted"#
+        )
+            .bind(domain_id)
+            .bind(code)
+            .bind(owner_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(url_id) = url_id else {
+            return Ok(None);
+        };
+        // old targets stay behind, soft-deleted, for the click stats
+        sqlx::query(r#"UPDATE link_targets SET is_deleted = TRUE WHERE url_id = ? AND NOT is_deleted"#)
+            .bind(url_id)
+            .execute(&mut *tx)
+            .await?;
+        let now = ts(Utc::now());
+        let mut saved = Vec::with_capacity(targets.len());
+        for t in targets {
+            let id: i64 = sqlx::query_scalar(
+                r#"INSERT INTO link_targets (url_id, target_url, weight, country, device, created_at) VALUES (?, ?, ?, ?, ?, ?) RETURNING id"#
+            )
+                .bind(url_id)
+                .bind(&t.url)
+                .bind(t.weight)
+                .bind(&t.country)
+                .bind(&t.device)
+                .bind(&now)
+                .fetch_one(&mut *tx)
+                .await?;
+            saved.push(LinkTarget{id, ..t.clone()});
+        }
+        tx.commit().await?;
+        Ok(Some(saved))
+    }
+
+    async fn list_links(&self, owner_id:i64, filter:&ListFilter)->StoreResult<Vec<ListedLink>>{
+        let (after_created, after_id) = filter.after.unzip();
+        sqlx::query_as(
+            r#"SELECT id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url, created_at, expires_at, is_deleted,
+                      (expires_at IS NOT NULL AND expires_at <= ?2) OR (max_clicks IS NOT NULL AND uses >= max_clicks) AS expired,
+                      clicks, redirect_status, password_hash IS NOT NULL AS password_protected, max_clicks
+               FROM urls
+               WHERE owner_id = ?1
+                 AND (?3 IS NULL OR (created_at, id) < (?3, ?4))
+                 AND is_deleted = ?5
+                 AND (?6 IS NULL OR ((expires_at IS NOT NULL AND expires_at <= ?2) OR (max_clicks IS NOT NULL AND uses >= max_clicks)) = ?6)
+                 AND (?7 IS NULL OR original_url LIKE ?7 ESCAPE '\')
+               ORDER BY created_at DESC, id DESC
+               LIMIT ?8"#
+        )
+            .bind(owner_id)
+            .bind(ts(Utc::now()))
+            .bind(after_created.map(ts))
+            .bind(after_id)
+            .bind(filter.deleted)
+            .bind(filter.expired)
+            .bind(&filter.pattern)
+            .bind(filter.limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn link_info(&self, owner_id:Option<i64>, domain_id:Option<i64>, code:&str)->StoreResult<Option<(i64, LinkInfo)>>{
+        let row: Option<InfoRow> = sqlx::query_as(
+            r#"SELECT id, short_code AS code, original_url, created_at, expires_at, is_deleted, clicks,
+                      password_hash IS NOT NULL AS password_protected, max_clicks, uses, query_passthrough
+               FROM urls WHERE COALESCE(domain_id, 0) = COALESCE(?3, 0) AND short_code = ?1 AND (?2 IS NULL OR owner_id = ?2)"#
+        )
+            .bind(code)
+            .bind(owner_id)
+            .bind(domain_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| (r.id, r.info)))
+    }
+
+    async fn click_breakdown(&self, url_id:i64, since:DateTime<Utc>, top:i64)->StoreResult<ClickBreakdown>{
+        let since = ts(since);
+        let daily: Vec<DailyClicks> = sqlx::query_as(
+            r#"SELECT substr(created_at, 1, 10) AS day, count(*) AS clicks
+               FROM clicks WHERE url_id = ? AND created_at >= ?
+               GROUP BY 1 ORDER BY 1"#
+        )
+            .bind(url_id)
+            .bind(&since)
+            .fetch_all(&self.pool)
+            .await?;
+        let top_referers: Vec<TopEntry> = sqlx::query_as(
+            r#"SELECT referer AS value, count(*) AS clicks
+               FROM clicks WHERE url_id = ? AND created_at >= ? AND referer IS NOT NULL
+               GROUP BY referer ORDER BY 2 DESC, 1 LIMIT ?"#
+        )
+            .bind(url_id)
+            .bind(&since)
+            .bind(top)
+            .fetch_all(&self.pool)
+            .await?;
+        let top_user_agents: Vec<TopEntry> = sqlx::query_as(
+            r#"SELECT user_agent AS value, count(*) AS clicks
+               FROM clicks WHERE url_id = ? AND created_at >= ? AND user_agent IS NOT NULL
+               GROUP BY user_agent ORDER BY 2 DESC, 1 LIMIT ?"#
+        )
+            .bind(url_id)
+            .bind(&since)
+            .bind(top)
+            .fetch_all(&self.pool)
+            .await?;
+        // targets without clicks in the window only show while they are live
+        let targets: Vec<TargetClicks> = sqlx::query_as(
+            r#"SELECT t.id AS target_id, t.target_url AS url, NOT t.is_deleted AS active, count(c.id) AS clicks
+               FROM link_targets t
+               LEFT JOIN clicks c ON c.target_id = t.id AND c.created_at >= ?2
+               WHERE t.url_id = ?1
+               GROUP BY t.id
+               HAVING NOT t.is_deleted OR count(c.id) > 0
+               ORDER BY t.id"#
+        )
+            .bind(url_id)
+            .bind(&since)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(ClickBreakdown{daily, top_referers, top_user_agents, targets})
+    }
+
+    async fn sweep_expired(&self)->StoreResult<Vec<LinkRef>>{
+        sqlx::query_as(
+            r#"UPDATE urls SET expiry_swept = TRUE
+               WHERE expires_at <= ? AND NOT expiry_swept AND NOT is_deleted
+               RETURNING owner_id, (SELECT host FROM domains WHERE id = urls.domain_id) AS domain, short_code AS code, original_url"#
+        )
+            .bind(ts(Utc::now()))
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn export_links(&self)->StoreResult<Vec<CsvLink>>{
+        sqlx::query_as(
+            r#"SELECT u.short_code, u.original_url, u.created_at, u.expires_at, u.is_deleted, u.clicks, o.name AS owner, u.redirect_status,
+                      d.host AS domain, u.password_hash, u.max_clicks, u.uses, u.query_passthrough
+               FROM urls u LEFT JOIN owners o ON o.id = u.owner_id LEFT JOIN domains d ON d.id = u.domain_id
+               ORDER BY u.id"#
+        )
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_domain(&self, owner_name:&str, host:&str)->StoreResult<Option<Domain>>{
+        let mut tx = self.pool.begin().await?;
+        let owner_id = upsert_owner(&mut tx, owner_name).await?;
+        let domain = sqlx::query_as(
+            r#"INSERT INTO domains (owner_id, host, created_at) VALUES (?, ?, ?)
+               ON CONFLICT (host) DO NOTHING RETURNING id, owner_id, host, created_at"#
+        )
+            .bind(owner_id)
+            .bind(host)
+            .bind(ts(Utc::now()))
+            .fetch_optional(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(domain)
+    }
+
+    async fn list_domains(&self, owner_id:Option<i64>)->StoreResult<Vec<Domain>>{
+        sqlx::query_as(r#"SELECT id, owner_id, host, created_at FROM domains WHERE ?1 IS NULL OR owner_id = ?1 ORDER BY host"#)
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_domain(&self, host:&str)->StoreResult<bool>{
+        let done = sqlx::query(r#"DELETE FROM domains WHERE host = ? AND NOT EXISTS (SELECT 1 FROM urls WHERE domain_id = domains.id)"#)
+            .bind(host)
+            .execute(&self.pool)
+            .await?;
+        Ok(done.rows_affected() > 0)
+    }
+
+    async fn create_webhook(&self, owner_id:i64, url:&str, secret:&str, events:&[String], click_thresholds:&[i64])->StoreResult<Webhook>{
+        sqlx::query_as(
+            r#"INSERT INTO webhooks (owner_id, url, secret, events, click_thresholds, created_at) VALUES (?, ?, ?, ?, ?, ?)
+               RETURNING id, url, events, click_thresholds, created_at"#
+        )
+            .bind(owner_id)
+            .bind(url)
+            .bind(secret)
+            .bind(sqlx::types::Json(events))
+            .bind(sqlx::types::Json(click_thresholds))
+            .bind(ts(Utc::now()))
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn list_webhooks(&self, owner_id:i64)->StoreResult<Vec<Webhook>>{
+        sqlx::query_as(
+            r#"SELECT id, url, events, click_thresholds, created_at FROM webhooks
+               WHERE owner_id = ? AND NOT is_deleted ORDER BY id"#
+        )
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_webhook(&self, owner_id:i64, id:i64)->StoreResult<bool>{
+        let mut tx = self.pool.begin().await?;
+        let done = sqlx::query(r#"UPDATE webhooks SET is_deleted = TRUE WHERE id = ? AND owner_id = ? AND NOT is_deleted"#)
+            .bind(id)
+            .bind(owner_id)
+            .execute(&mut *tx)
+            .await?;
+        if done.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(r#"DELETE FROM webhook_deliveries WHERE webhook_id = ?"#).bind(id).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn subscriptions(&self, owner_ids:&[i64], event:&str)->StoreResult<Vec<Subscription>>{
+        sqlx::query_as(
+            r#"SELECT id, owner_id, click_thresholds FROM webhooks
+               WHERE owner_id IN (SELECT value FROM json_each(?1))
+                 AND EXISTS (SELECT 1 FROM json_each(events) WHERE value = ?2) AND NOT is_deleted"#
+        )
+            .bind(sqlx::types::Json(owner_ids))
+            .bind(event)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn enqueue_deliveries(&self, deliveries:&[NewDelivery])->StoreResult<()>{
+        let now = ts(Utc::now());
+        let mut tx = self.pool.begin().await?;
+        for d in deliveries {
+            sqlx::query(r#"INSERT INTO webhook_deliveries (webhook_id, event, payload, next_attempt_at, created_at) VALUES (?1, ?2, ?3, ?4, ?4)"#)
+                .bind(d.webhook_id)
+                .bind(d.event)
+                .bind(sqlx::types::Json(&d.payload))
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn claim_deliveries(&self, limit:i64, lease:Duration)->StoreResult<Vec<Delivery>>{
+        let now = Utc::now();
+        sqlx::query_as(
+            r#"UPDATE webhook_deliveries SET attempts = attempts + 1, next_attempt_at = ?2
+               WHERE id IN (SELECT id FROM webhook_deliveries WHERE next_attempt_at <= ?1 ORDER BY next_attempt_at LIMIT ?3)
+               RETURNING id, webhook_id, event, payload, attempts,
+                         (SELECT url FROM webhooks WHERE id = webhook_id) AS url,
+                         (SELECT secret FROM webhooks WHERE id = webhook_id) AS secret"#
+        )
+            .bind(ts(now))
+            .bind(ts(now + lease))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delivery_succeeded(&self, id:i64)->StoreResult<()>{
+        sqlx::query(r#"DELETE FROM webhook_deliveries WHERE id = ?"#).bind(id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delivery_failed(&self, id:i64, error:&str, retry_at:DateTime<Utc>)->StoreResult<()>{
+        sqlx::query(r#"UPDATE webhook_deliveries SET last_error = ?, next_attempt_at = ? WHERE id = ?"#)
+            .bind(error)
+            .bind(ts(retry_at))
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn dead_letter(&self, id:i64, error:&str)->StoreResult<()>{
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"INSERT INTO webhook_dead_letters (webhook_id, event, payload, attempts, last_error, created_at, failed_at)
+               SELECT webhook_id, event, payload, attempts, ?2, created_at, ?3 FROM webhook_deliveries WHERE id = ?1"#
+        )
+            .bind(id)
+            .bind(error)
+            .bind(ts(Utc::now()))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(r#"DELETE FROM webhook_deliveries WHERE id = ?"#).bind(id).execute(&mut *tx).await?;
+        tx.commit().await
+    }
+
+    async fn dead_letters(&self, owner_id:i64, webhook_id:Option<i64>, limit:i64)->StoreResult<Vec<DeadLetter>>{
+        sqlx::query_as(
+            r#"SELECT l.id, l.webhook_id, l.event, l.payload, l.attempts, l.last_error, l.created_at, l.failed_at
+               FROM webhook_dead_letters l JOIN webhooks w ON w.id = l.webhook_id
+               WHERE w.owner_id = ?1 AND (?2 IS NULL OR l.webhook_id = ?2)
+               ORDER BY l.id DESC LIMIT ?3"#
+        )
+            .bind(owner_id)
+            .bind(webhook_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+struct SqliteTx{
+    tx: Transaction<'static, Sqlite>,
+}
+
+#[async_trait]
+impl LinkTx for SqliteTx {
+    async fn find_reusable(&mut self, owner_id:i64, domain_id:Option<i64>, url:&str)->StoreResult<Option<ExistingLink>>{
+        sqlx::query_as(
+            r#"SELECT short_code AS code, expires_at FROM urls
+               WHERE original_url = ? AND owner_id = ? AND COALESCE(domain_id, 0) = COALESCE(?, 0)
+                 AND NOT is_deleted AND (expires_at IS NULL OR expires_at > ?)
+                 AND password_hash IS NULL AND max_clicks IS NULL AND NOT query_passthrough
+               ORDER BY created_at DESC LIMIT 1"#
+        )
+            .bind(url)
+            .bind(owner_id)
+            .bind(domain_id)
+            .bind(ts(Utc::now()))
+            .fetch_optional(&mut *self.tx)
+            .await
+    }
+
+    async fn next_sequence(&mut self)->StoreResult<i64>{
+        sqlx::query_scalar(r#"UPDATE short_code_seq SET next_value = next_value + 1 RETURNING next_value - 1"#)
+            .fetch_one(&mut *self.tx)
+            .await
+    }
+
+    async fn insert_link(&mut self, owner_id:i64, code:&str, row:&NewRow<'_>)->StoreResult<bool>{
+        let inserted: Option<i64> = sqlx::query_scalar(
+            r#"INSERT INTO urls (short_code, original_url, created_at, expires_at, owner_id, redirect_status, password_hash, max_clicks, domain_id, query_passthrough)
+               VALUES (?, ?, ?, ?, ?, COALESCE(?, 302), ?, ?, ?, ?)
+               ON CONFLICT (COALESCE(domain_id, 0), short_code) DO NOTHING RETURNING id"#
+        )
+            .bind(code)
+            .bind(row.url)
+            .bind(ts(Utc::now()))
+            .bind(row.expires_at.map(ts))
+            .bind(owner_id)
+            .bind(row.redirect_status)
+            .bind(row.password_hash)
+            .bind(row.max_clicks)
+            .bind(row.domain_id)
+            .bind(row.query_passthrough)
+            .fetch_optional(&mut *self.tx)
+            .await?;
+        Ok(inserted.is_some())
+    }
+
+    async fn owner_id(&mut self, name:&str)->StoreResult<i64>{
+        upsert_owner(&mut self.tx, name).await
+    }
+
+    async fn domain(&mut self, host:&str)->StoreResult<Option<Domain>>{
+        sqlx::query_as(r#"SELECT id, owner_id, host, created_at FROM domains WHERE host = ?"#)
+            .bind(host)
+            .fetch_optional(&mut *self.tx)
+            .await
+    }
+
+    async fn import_link(&mut self, owner_id:Option<i64>, domain_id:Option<i64>, link:&CsvLink)->StoreResult<bool>{
+        let inserted: Option<i64> = sqlx::query_scalar(
+            r#"INSERT INTO urls (short_code, original_url, created_at, expires_at, is_deleted, clicks, owner_id, redirect_status, domain_id,
+                                 password_hash, max_clicks, uses, query_passthrough)
+               VALUES (?, ?, ?, ?, COALESCE(?, FALSE), COALESCE(?, 0), ?, COALESCE(?, 302), ?, ?, ?, COALESCE(?, 0), COALESCE(?, FALSE))
+               ON CONFLICT (COALESCE(domain_id, 0), short_code) DO NOTHING RETURNING id"#
+        )
+            .bind(&link.short_code)
+            .bind(&link.original_url)
+            .bind(ts(link.created_at.unwrap_or_else(Utc::now)))
+            .bind(link.expires_at.map(ts))
+            .bind(link.is_deleted)
+            .bind(link.clicks)
+            .bind(owner_id)
+            .bind(link.redirect_status)
+            .bind(domain_id)
+            .bind(&link.password_hash)
+            .bind(link.max_clicks)
+            .bind(link.uses)
+            .bind(link.query_passthrough)
+            .fetch_optional(&mut *self.tx)
+            .await?;
+        Ok(inserted.is_some())
+    }
+
+    async fn commit(self:Box<Self>)->StoreResult<()>{
+        self.tx.commit().await
+    }
+}
+
+async fn upsert_owner(tx:&mut Transaction<'static, Sqlite>, name:&str)->StoreResult<i64>{
+    sqlx::query_scalar(
+        r#"INSERT INTO owners (name, created_at) VALUES (?, ?)
+           ON CONFLICT (name) DO UPDATE SET name = excluded.name
+           RETURNING id"#
+    )
+        .bind(name)
+        .bind(ts(Utc::now()))
+        .fetch_one(&mut **tx)
+        .await
+}
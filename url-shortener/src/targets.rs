@@ -0,0 +1,116 @@
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap},
+    Extension, Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{evict_cached_link, AppState};
+use crate::auth::Owner;
+use crate::domains::DomainParam;
+use crate::errors::AppError;
+
+pub const MAX_TARGETS: usize = 20;
+const MAX_WEIGHT: i32 = 10_000;
+
+/// One alternative destination of a link. A target with a country or device only serves
+/// requests that match it.
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LinkTarget{
+    pub id: i64,
+    pub url: String,
+    pub weight: i32,
+    pub country: Option<String>,
+    pub device: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TargetReq{
+    pub url: String,
+    pub weight: Option<i32>,
+    pub country: Option<String>,
+    pub device: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TargetsReq{
+    /// replaces the current targets; an empty list turns the split off
+    pub targets: Vec<TargetReq>,
+}
+
+#[derive(Serialize)]
+pub struct TargetsResp{
+    code: String,
+    targets: Vec<LinkTarget>,
+}
+
+/// Picks the target for a request: among the targets whose conditions all match, only the most
+/// specific ones are considered and one of them is drawn by weight. `None` means the link's
+/// own url.
+pub fn choose<'a>(targets:&'a [LinkTarget], country:Option<&str>, device:&str)->Option<&'a LinkTarget>{
+    let matching: Vec<&LinkTarget> = targets.iter()
+        .filter(|t| t.weight > 0)
+        .filter(|t| t.country.as_deref().is_none_or(|c| Some(c) == country))
+        .filter(|t| t.device.as_deref().is_none_or(|d| d == device))
+        .collect();
+    let specificity = |t:&LinkTarget| t.country.is_some() as u8 * 2 + t.device.is_some() as u8;
+    let best = matching.iter().map(|t| specificity(t)).max()?;
+    let candidates: Vec<&LinkTarget> = matching.into_iter().filter(|t| specificity(t) == best).collect();
+    let total: i64 = candidates.iter().map(|t| t.weight as i64).sum();
+    let mut pick = rand::thread_rng().gen_range(0..total);
+    for target in candidates {
+        if pick < target.weight as i64 {
+            return Some(target);
+        }
+        pick -= target.weight as i64;
+    }
+    None
+}
+
+/// Coarse device class from the User-Agent: mobile, tablet or desktop.
+pub fn device_class(headers:&HeaderMap)->&'static str{
+    let ua = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if ua.contains("iPad") || ua.contains("Tablet") || (ua.contains("Android") && !ua.contains("Mobile")) {
+        "tablet"
+    } else if ua.contains("Mobi") || ua.contains("iPhone") || ua.contains("Android") {
+        "mobile"
+    } else {
+        "desktop"
+    }
+}
+
+pub async fn put_targets(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(code): Path<String>, Query(at): Query<DomainParam>, Json(payload): Json<TargetsReq>) -> Result<Json<TargetsResp>, AppError> {
+    let domain = state.domains.owned(Some(owner.id), at.domain.as_deref())?;
+    let host = domain.as_ref().map(|d| d.host.as_str());
+    if payload.targets.len() > MAX_TARGETS {
+        return Err(AppError::BadRequest(format!("at most {} targets per link", MAX_TARGETS)));
+    }
+    let mut targets = Vec::with_capacity(payload.targets.len());
+    for target in payload.targets {
+        targets.push(validate_target(&state, target)?);
+    }
+
+    // old targets stay behind, soft-deleted, for the click stats
+    let saved = state.store.replace_targets(owner.id, domain.as_ref().map(|d| d.id), &code, &targets)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    evict_cached_link(&state, host, &code).await;
+    Ok(Json(TargetsResp{code, targets: saved}))
+}
+
+fn validate_target(state:&AppState, target:TargetReq)->Result<LinkTarget, AppError>{
+    let url = state.url_policy.check(&target.url)?;
+    let weight = target.weight.unwrap_or(1);
+    if !(0..=MAX_WEIGHT).contains(&weight) {
+        return Err(AppError::BadRequest(format!("weight must be between 0 and {}", MAX_WEIGHT)));
+    }
+    let country = target.country.map(|c| c.to_ascii_uppercase());
+    if let Some(c) = &country && !(c.len() == 2 && c.bytes().all(|b| b.is_ascii_alphabetic())) {
+        return Err(AppError::BadRequest("country must be a two-letter ISO 3166 code".into()));
+    }
+    if let Some(d) = &target.device && !matches!(d.as_str(), "mobile" | "tablet" | "desktop") {
+        return Err(AppError::BadRequest("device must be mobile, tablet or desktop".into()));
+    }
+    Ok(LinkTarget{id: 0, url, weight, country, device: target.device})
+}
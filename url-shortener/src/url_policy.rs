@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use thiserror::Error;
+use url::{Host, Url};
+
+use crate::config::Config;
+use crate::domains::DomainMap;
+
+#[derive(Debug, Error)]
+pub enum UrlError {
+    #[error("invalid url")]
+    Invalid,
+
+    #[error("url scheme {0:?} is not allowed")]
+    Scheme(String),
+
+    #[error("domain {0:?} is blocked")]
+    BlockedDomain(String),
+
+    #[error("url points back to this service")]
+    SelfReferential,
+
+    #[error("url points to a loopback or private address")]
+    PrivateAddress,
+}
+
+impl UrlError {
+    pub fn rule(&self) -> &'static str {
+        match self {
+            UrlError::Invalid => "invalid",
+            UrlError::Scheme(_) => "scheme",
+            UrlError::BlockedDomain(_) => "blocked_domain",
+            UrlError::SelfReferential => "self_referential",
+            UrlError::PrivateAddress => "private_address",
+        }
+    }
+}
+
+/// One check a destination url has to pass.
+pub trait UrlRule: Send + Sync {
+    fn check(&self, url:&Url)->Result<(), UrlError>;
+}
+
+/// The rules every link destination goes through, in order; the first violation wins.
+#[derive(Clone)]
+pub struct UrlPolicy{
+    rules: Vec<Arc<dyn UrlRule>>,
+}
+
+impl UrlPolicy {
+    /// Scheme allowlist, optional domain blocklist file, then self-reference and private address
+    /// checks. `domains` are our custom short domains, refused like base_url's host.
+    pub fn from_config(config:&Config, domains:Arc<DomainMap>)->anyhow::Result<Self>{
+        let mut policy = UrlPolicy{rules: Vec::new()}
+            .with_rule(SchemeAllowlist(config.allowed_schemes.iter().cloned().collect()));
+        if let Some(path) = &config.blocked_domains_file {
+            policy = policy.with_rule(DomainBlocklist::from_file(path)?);
+        }
+        Ok(policy.with_rule(SelfReference::new(config, domains)?).with_rule(PrivateAddress))
+    }
+
+    /// Webhook endpoints: http(s) only, never the shortener itself, and no private addresses
+    /// unless `webhook_allow_private` is set.
+    pub fn for_webhooks(config:&Config, domains:Arc<DomainMap>)->anyhow::Result<Self>{
+        let schemes = ["http", "https"].into_iter().map(String::from).collect();
+        let policy = UrlPolicy{rules: Vec::new()}
+            .with_rule(SchemeAllowlist(schemes))
+            .with_rule(SelfReference::new(config, domains)?.origin_only(config)?);
+        if config.webhook_allow_private {
+            Ok(policy)
+        } else {
+            Ok(policy.with_rule(PrivateAddress))
+        }
+    }
+
+    pub fn with_rule(mut self, rule:impl UrlRule + 'static)->Self{
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Parses and checks a destination, returning it in normalized form.
+    pub fn check(&self, raw:&str)->Result<String, UrlError>{
+        let url = Url::parse(raw).map_err(|_| UrlError::Invalid)?;
+        for rule in &self.rules {
+            rule.check(&url)?;
+        }
+        Ok(url.to_string())
+    }
+}
+
+pub struct SchemeAllowlist(pub HashSet<String>);
+
+impl UrlRule for SchemeAllowlist {
+    fn check(&self, url:&Url)->Result<(), UrlError>{
+        if self.0.contains(url.scheme()) {
+            Ok(())
+        } else {
+            Err(UrlError::Scheme(url.scheme().to_string()))
+        }
+    }
+}
+
+/// Blocks listed domains and all their subdomains.
+pub struct DomainBlocklist{
+    domains: HashSet<String>,
+}
+
+impl DomainBlocklist {
+    /// One domain per line; blank lines and `#` comments are skipped, a leading `*.` is ignored.
+    pub fn from_file(path:&str)->anyhow::Result<Self>{
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read blocked domains file {}: {}", path, e))?;
+        let mut domains = HashSet::new();
+        for (n, line) in raw.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let entry = entry.trim_start_matches("*.").trim_end_matches('.');
+            // same normalization (lowercase, punycode) the url parser applies to hosts
+            match Host::parse(entry) {
+                Ok(Host::Domain(domain)) => domains.insert(domain),
+                _ => anyhow::bail!("{}:{}: {:?} is not a domain", path, n + 1, entry),
+            };
+        }
+        tracing::info!("loaded {} blocked domains from {}", domains.len(), path);
+        Ok(DomainBlocklist{domains})
+    }
+}
+
+impl UrlRule for DomainBlocklist {
+    fn check(&self, url:&Url)->Result<(), UrlError>{
+        let Some(Host::Domain(host)) = url.host() else {
+            return Ok(());
+        };
+        let host = host.trim_end_matches('.');
+        // walk up the labels: a.b.example.com, b.example.com, example.com, com
+        let mut candidate = host;
+        loop {
+            if self.domains.contains(candidate) {
+                return Err(UrlError::BlockedDomain(host.to_string()));
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Refuses links to the shortener itself, on base_url or one of the custom domains, which
+/// would redirect in circles or hide where a link really goes.
+pub struct SelfReference{
+    host: String,
+    /// base_url's port when only it counts, not other services on the same host
+    port: Option<u16>,
+    domains: Arc<DomainMap>,
+}
+
+impl SelfReference {
+    pub fn new(config:&Config, domains:Arc<DomainMap>)->anyhow::Result<Self>{
+        let host = Url::parse(&config.base_url)?
+            .host_str()
+            .map(|h| h.trim_end_matches('.').to_ascii_lowercase())
+            .ok_or_else(|| anyhow::anyhow!("base_url has no host"))?;
+        Ok(SelfReference{host, port: None, domains})
+    }
+
+    /// Refuses base_url's host only on base_url's port.
+    pub fn origin_only(mut self, config:&Config)->anyhow::Result<Self>{
+        self.port = Url::parse(&config.base_url)?.port_or_known_default();
+        Ok(self)
+    }
+}
+
+impl UrlRule for SelfReference {
+    fn check(&self, url:&Url)->Result<(), UrlError>{
+        let Some(host) = url.host_str() else {
+            return Ok(());
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let same_origin = host == self.host && self.port.is_none_or(|port| url.port_or_known_default() == Some(port));
+        if same_origin || self.domains.contains(&host) {
+            Err(UrlError::SelfReferential)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Refuses localhost names and loopback, private and link-local ip literals. Hostnames are not
+/// resolved here, so a public name pointing at a private address still passes; webhook
+/// deliveries check the resolved addresses with `PublicResolver`.
+pub struct PrivateAddress;
+
+impl UrlRule for PrivateAddress {
+    fn check(&self, url:&Url)->Result<(), UrlError>{
+        let private = match url.host() {
+            Some(Host::Domain(host)) => {
+                let host = host.trim_end_matches('.');
+                host == "localhost" || host.ends_with(".localhost")
+            }
+            Some(Host::Ipv4(ip)) => is_private_v4(ip),
+            Some(Host::Ipv6(ip)) => is_private_v6(ip),
+            None => false,
+        };
+        if private {
+            Err(UrlError::PrivateAddress)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Resolves webhook hosts and refuses names with any private address, so a public name pointing
+/// inside the network cannot reach internal services. The checked addresses are the ones the
+/// client connects to, so a second lookup cannot swap them.
+pub struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name:reqwest::dns::Name)->reqwest::dns::Resolving{
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| is_private_ip(addr.ip())) {
+                return Err(format!("{} resolves to a loopback or private address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn is_private_ip(ip:IpAddr)->bool{
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => is_private_v6(ip),
+    }
+}
+
+fn is_private_v4(ip:Ipv4Addr)->bool{
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+        // carrier-grade nat, 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64)
+}
+
+fn is_private_v6(ip:Ipv6Addr)->bool{
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_private_v4(v4);
+    }
+    let segments = ip.segments();
+    // nat64 64:ff9b::/96 and 6to4 2002::/16 reach the ipv4 address they embed
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_private_v4(Ipv4Addr::from(u128::from(ip) as u32));
+    }
+    if segments[0] == 0x2002 {
+        return is_private_v4(Ipv4Addr::from(((segments[1] as u32) << 16) | segments[2] as u32));
+    }
+    let first = segments[0];
+    ip.is_loopback() || ip.is_unspecified()
+        // unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
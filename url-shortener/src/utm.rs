@@ -0,0 +1,77 @@
+use serde::Deserialize;
+use url::{form_urlencoded, Url};
+
+use crate::errors::AppError;
+
+const MAX_UTM_LEN: usize = 256;
+
+/// Campaign parameters added to the destination when a link is created.
+#[derive(Deserialize, Default)]
+pub struct Utm{
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+}
+
+impl Utm {
+    fn pairs(&self)->Vec<(&'static str, &str)>{
+        [
+            ("utm_source", &self.utm_source),
+            ("utm_medium", &self.utm_medium),
+            ("utm_campaign", &self.utm_campaign),
+            ("utm_term", &self.utm_term),
+            ("utm_content", &self.utm_content),
+        ].into_iter()
+            .filter_map(|(key, value)| value.as_deref().map(|v| (key, v)))
+            .collect()
+    }
+
+    /// Appends the fields to `url`. A field replaces every parameter of the same name already
+    /// on the url; the url's other parameters keep their order and encoding.
+    pub fn apply(&self, url:&str)->Result<String, AppError>{
+        let pairs = self.pairs();
+        if pairs.is_empty() {
+            return Ok(url.to_string());
+        }
+        for (key, value) in &pairs {
+            if value.trim().is_empty() || value.len() > MAX_UTM_LEN {
+                return Err(AppError::BadRequest(format!("{} must be 1 to {} bytes", key, MAX_UTM_LEN)));
+            }
+        }
+        let mut parsed = Url::parse(url).map_err(|_| AppError::BadRequest("invalid url".into()))?;
+        let replaced = parsed.query_pairs().any(|(k, _)| pairs.iter().any(|(key, _)| k == *key));
+        if replaced {
+            let kept: Vec<(String, String)> = parsed.query_pairs()
+                .filter(|(k, _)| !pairs.iter().any(|(key, _)| k == *key))
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect();
+            parsed.set_query(None);
+            if !kept.is_empty() {
+                parsed.query_pairs_mut().extend_pairs(kept);
+            }
+        }
+        parsed.query_pairs_mut().extend_pairs(pairs);
+        Ok(parsed.into())
+    }
+}
+
+/// Merges the short url's query string into a passthrough link's destination. The destination
+/// wins on conflicts: a parameter it already has is never overridden or repeated, whatever the
+/// visitor sends. Other parameters are appended in the order given, repeated ones included.
+pub fn merge_query(destination:&str, query:&str)->String{
+    let Ok(mut url) = Url::parse(destination) else {
+        return destination.to_string();
+    };
+    let own: Vec<String> = url.query_pairs().map(|(k, _)| k.into_owned()).collect();
+    let extra: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .filter(|(k, _)| !k.is_empty() && !own.iter().any(|o| o == k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if extra.is_empty() {
+        return destination.to_string();
+    }
+    url.query_pairs_mut().extend_pairs(extra);
+    url.into()
+}
@@ -0,0 +1,351 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::task::JoinSet;
+
+use crate::api::{short_url, AppState};
+use crate::auth::Owner;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::store::{ClickTotal, LinkRef, LinkStore};
+use crate::url_policy::PublicResolver;
+
+pub const LINK_CREATED: &str = "link.created";
+pub const LINK_DELETED: &str = "link.deleted";
+pub const LINK_EXPIRED: &str = "link.expired";
+/// sent once per subscribed threshold a link's click count reaches
+pub const LINK_CLICKS: &str = "link.clicks";
+const EVENTS: [&str; 4] = [LINK_CREATED, LINK_DELETED, LINK_EXPIRED, LINK_CLICKS];
+
+const SECRET_PREFIX: &str = "whsec_";
+const SECRET_RANDOM_LEN: usize = 32;
+const MAX_WEBHOOKS: usize = 20;
+const MAX_THRESHOLDS: usize = 20;
+const DEFAULT_DEAD_LETTERS: i64 = 50;
+const MAX_DEAD_LETTERS: i64 = 200;
+
+const CLAIM_BATCH: i64 = 50;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// A subscription as the api shows it; the signing secret is only returned on creation.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Webhook{
+    pub id: i64,
+    pub url: String,
+    #[sqlx(json)]
+    pub events: Vec<String>,
+    #[sqlx(json)]
+    pub click_thresholds: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What event fan-out needs to know about a subscribed webhook.
+#[derive(sqlx::FromRow)]
+pub struct Subscription{
+    pub id: i64,
+    pub owner_id: i64,
+    #[sqlx(json)]
+    pub click_thresholds: Vec<i64>,
+}
+
+pub struct NewDelivery{
+    pub webhook_id: i64,
+    pub event: &'static str,
+    pub payload: serde_json::Value,
+}
+
+/// A claimed delivery with what is needed to send it. `attempts` counts this one.
+#[derive(sqlx::FromRow)]
+pub struct Delivery{
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    #[sqlx(json)]
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+/// A delivery that used up its attempts.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DeadLetter{
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    #[sqlx(json)]
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookReq{
+    pub url: String,
+    pub events: Vec<String>,
+    /// click counts that trigger link.clicks; required when subscribing to it
+    #[serde(default)]
+    pub click_thresholds: Vec<i64>,
+}
+
+#[derive(Serialize)]
+pub struct CreateWebhookResp{
+    #[serde(flatten)]
+    webhook: Webhook,
+    /// signs every delivery, see `sign`
+    secret: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhooksResp{
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Deserialize)]
+pub struct DeadLettersQuery{
+    pub webhook_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct DeadLettersResp{
+    dead_letters: Vec<DeadLetter>,
+}
+
+pub async fn create_webhook(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Json(payload): Json<CreateWebhookReq>) -> Result<(StatusCode, Json<CreateWebhookResp>), AppError> {
+    let url = state.webhook_policy.check(&payload.url)?;
+    let mut events = payload.events;
+    events.sort();
+    events.dedup();
+    if events.is_empty() {
+        return Err(AppError::BadRequest("events must not be empty".into()));
+    }
+    if let Some(unknown) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        return Err(AppError::BadRequest(format!("unknown event {:?}, expected one of {}", unknown, EVENTS.join(", "))));
+    }
+    let mut thresholds = payload.click_thresholds;
+    thresholds.sort_unstable();
+    thresholds.dedup();
+    let wants_clicks = events.iter().any(|e| e == LINK_CLICKS);
+    if wants_clicks == thresholds.is_empty() {
+        return Err(AppError::BadRequest(format!("click_thresholds are required with {} and only allowed with it", LINK_CLICKS)));
+    }
+    if thresholds.len() > MAX_THRESHOLDS || thresholds.first().is_some_and(|t| *t < 1) {
+        return Err(AppError::BadRequest(format!("at most {} click_thresholds, each at least 1", MAX_THRESHOLDS)));
+    }
+    if state.store.list_webhooks(owner.id).await?.len() >= MAX_WEBHOOKS {
+        return Err(AppError::BadRequest(format!("at most {} webhooks per owner", MAX_WEBHOOKS)));
+    }
+
+    let secret = format!("{}{}", SECRET_PREFIX, crate::codegen::random_base62(SECRET_RANDOM_LEN));
+    let webhook = state.store.create_webhook(owner.id, &url, &secret, &events, &thresholds).await?;
+    Ok((StatusCode::CREATED, Json(CreateWebhookResp{webhook, secret})))
+}
+
+pub async fn list_webhooks(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>) -> Result<Json<WebhooksResp>, AppError> {
+    let webhooks = state.store.list_webhooks(owner.id).await?;
+    Ok(Json(WebhooksResp{webhooks}))
+}
+
+pub async fn delete_webhook(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Path(id): Path<i64>) -> Result<StatusCode, AppError> {
+    if !state.store.delete_webhook(owner.id, id).await? {
+        return Err(AppError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn dead_letters(Extension(state): Extension<AppState>, Extension(owner): Extension<Owner>, Query(query): Query<DeadLettersQuery>) -> Result<Json<DeadLettersResp>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_DEAD_LETTERS);
+    if !(1..=MAX_DEAD_LETTERS).contains(&limit) {
+        return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_DEAD_LETTERS)));
+    }
+    let dead_letters = state.store.dead_letters(owner.id, query.webhook_id, limit).await?;
+    Ok(Json(DeadLettersResp{dead_letters}))
+}
+
+/// Queues `event` for every webhook of the links' owners that subscribes to it. Events are a
+/// side effect of a change that already happened, so failures are logged rather than returned.
+pub async fn emit(store:&dyn LinkStore, base_url:&str, event:&'static str, links:&[LinkRef]){
+    let result = async {
+        let Some(subscriptions) = subscriptions(store, event, links.iter()).await? else {
+            return Ok(());
+        };
+        let mut deliveries = Vec::new();
+        for link in links {
+            for sub in subscriptions.iter().filter(|s| Some(s.owner_id) == link.owner_id) {
+                deliveries.push(NewDelivery{webhook_id: sub.id, event, payload: payload(event, link_data(base_url, link))});
+            }
+        }
+        store.enqueue_deliveries(&deliveries).await
+    }.await;
+    if let Err(e) = result {
+        tracing::error!("failed to queue {} webhooks: {:?}", event, e);
+    }
+}
+
+/// Queues link.clicks for every subscribed threshold a batch of clicks took a link to or past.
+pub async fn emit_click_totals(store:&dyn LinkStore, base_url:&str, totals:&[ClickTotal]){
+    let result = async {
+        let Some(subscriptions) = subscriptions(store, LINK_CLICKS, totals.iter().map(|t| &t.link)).await? else {
+            return Ok(());
+        };
+        let mut deliveries = Vec::new();
+        for total in totals {
+            let before = total.clicks - total.added;
+            for sub in subscriptions.iter().filter(|s| Some(s.owner_id) == total.link.owner_id) {
+                for threshold in sub.click_thresholds.iter().filter(|t| before < **t && **t <= total.clicks) {
+                    let mut data = link_data(base_url, &total.link);
+                    data["clicks"] = json!(total.clicks);
+                    data["threshold"] = json!(threshold);
+                    deliveries.push(NewDelivery{webhook_id: sub.id, event: LINK_CLICKS, payload: payload(LINK_CLICKS, data)});
+                }
+            }
+        }
+        store.enqueue_deliveries(&deliveries).await
+    }.await;
+    if let Err(e) = result {
+        tracing::error!("failed to queue {} webhooks: {:?}", LINK_CLICKS, e);
+    }
+}
+
+// none when no owner of these links subscribes, which is the common case
+async fn subscriptions<'a>(store:&dyn LinkStore, event:&str, links:impl Iterator<Item = &'a LinkRef>)->sqlx::Result<Option<Vec<Subscription>>>{
+    let mut owner_ids: Vec<i64> = links.filter_map(|l| l.owner_id).collect();
+    owner_ids.sort_unstable();
+    owner_ids.dedup();
+    if owner_ids.is_empty() {
+        return Ok(None);
+    }
+    let subscriptions = store.subscriptions(&owner_ids, event).await?;
+    Ok((!subscriptions.is_empty()).then_some(subscriptions))
+}
+
+fn link_data(base_url:&str, link:&LinkRef)->serde_json::Value{
+    json!({
+        "code": link.code,
+        "short_url": short_url(base_url, link.domain.as_deref(), &link.code),
+        "original_url": link.original_url,
+    })
+}
+
+fn payload(event:&str, data:serde_json::Value)->serde_json::Value{
+    json!({
+        "id": uuid::Uuid::new_v4().to_string(),
+        "type": event,
+        "created_at": Utc::now(),
+        "data": data,
+    })
+}
+
+/// Hex HMAC-SHA256 of `"{timestamp}.{body}"`, sent as `X-Shorty-Signature: sha256=<hex>`.
+/// Receivers recompute it with their secret and reject stale timestamps to stop replays.
+pub fn sign(secret:&str, timestamp:i64, body:&[u8])->String{
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Clone, Copy)]
+struct RetryPolicy{
+    max_attempts: i32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    // doubles after every failed attempt
+    fn delay(&self, attempts:i32)->Duration{
+        let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        self.base_delay.saturating_mul(2u32.pow(exponent)).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// Sends queued deliveries in the background until the process exits.
+pub fn spawn_delivery(store:Arc<dyn LinkStore>, config:&Config)->anyhow::Result<()>{
+    let mut client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.webhook_timeout_secs))
+        // a redirect could point a delivery past the url policy
+        .redirect(reqwest::redirect::Policy::none());
+    if !config.webhook_allow_private {
+        // the policy only sees the name; check what it resolves to when connecting, and go
+        // direct since a proxy would do its own lookup
+        client = client.dns_resolver(Arc::new(PublicResolver)).no_proxy();
+    }
+    let client = client.build()?;
+    let retry = RetryPolicy{max_attempts: config.webhook_max_attempts, base_delay: Duration::from_secs(config.webhook_retry_secs)};
+    // a claimed delivery stays hidden from other workers until its request has surely timed out
+    let lease = Duration::from_secs(config.webhook_timeout_secs * 2 + 5);
+    tokio::spawn(deliver_loop(store, client, retry, lease));
+    Ok(())
+}
+
+async fn deliver_loop(store:Arc<dyn LinkStore>, client:reqwest::Client, retry:RetryPolicy, lease:Duration){
+    tracing::info!("webhook delivery started");
+    loop {
+        let batch = match store.claim_deliveries(CLAIM_BATCH, lease).await {
+            Ok(batch) => batch,
+            Err(e) => {
+                tracing::error!("db error while claiming webhook deliveries: {:?}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+        if batch.is_empty() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+        let mut sends = JoinSet::new();
+        for delivery in batch {
+            sends.spawn(deliver(store.clone(), client.clone(), retry, delivery));
+        }
+        while sends.join_next().await.is_some() {}
+    }
+}
+
+async fn deliver(store:Arc<dyn LinkStore>, client:reqwest::Client, retry:RetryPolicy, delivery:Delivery){
+    let body = delivery.payload.to_string().into_bytes();
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(&delivery.secret, timestamp, &body);
+    let sent = client.post(&delivery.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("x-shorty-event", &delivery.event)
+        .header("x-shorty-delivery", delivery.id)
+        .header("x-shorty-timestamp", timestamp)
+        .header("x-shorty-signature", format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await;
+    let error = match sent {
+        Ok(resp) if resp.status().is_success() => None,
+        Ok(resp) => Some(format!("http status {}", resp.status())),
+        Err(e) => Some(format!("{:#}", anyhow::Error::from(e))),
+    };
+    let done = match &error {
+        None => store.delivery_succeeded(delivery.id).await,
+        Some(error) if delivery.attempts >= retry.max_attempts => {
+            tracing::warn!("webhook {} gave up on delivery {} after {} attempts: {}", delivery.webhook_id, delivery.id, delivery.attempts, error);
+            store.dead_letter(delivery.id, error).await
+        }
+        Some(error) => {
+            let retry_at = Utc::now() + retry.delay(delivery.attempts);
+            store.delivery_failed(delivery.id, error, retry_at).await
+        }
+    };
+    // the lease runs out and the delivery is sent again
+    if let Err(e) = done {
+        tracing::error!("db error while recording webhook delivery {}: {:?}", delivery.id, e);
+    }
+}
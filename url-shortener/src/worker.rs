@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache::RedirectCache;
+use crate::config::Config;
+use crate::db;
+use crate::link_cache::RedisCache;
+use crate::retry::Backoff;
+use crate::redis_queue::{create_pool as create_redis_pool, pop_clicks, requeue_clicks};
+use crate::store::{self, LinkStore};
+use crate::webhooks;
+
+const BATCH_SIZE: usize = 500;
+const POP_WAIT_SECS: f64 = 5.0;
+const RETRY_INITIAL: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+pub async fn run(config:&Config)->anyhow::Result<()>{
+    if config.redis_url.is_empty() {
+        anyhow::bail!("the worker drains the redis click queue, set redis_url; without redis the api records clicks itself");
+    }
+    let store=store::connect(config).await?;
+    db::spawn_warm_up(store.clone());
+    let redis_pool=create_redis_pool(&config.redis_url, config.redis_pool_size).await?;
+    tracing::info!("click worker started");
+    let cache = RedirectCache::from_config(Arc::new(RedisCache::new(redis_pool.clone())), config)?;
+    tokio::spawn(sweep_expired(store.clone(), Arc::new(cache), config.base_url.clone(), Duration::from_secs(config.expiry_sweep_secs)));
+    webhooks::spawn_delivery(store.clone(), config)?;
+
+    let mut backoff = Backoff::new(RETRY_INITIAL, RETRY_MAX);
+    loop {
+        let events = match pop_clicks(&redis_pool, BATCH_SIZE, POP_WAIT_SECS).await {
+            Ok(events) => events,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                tracing::error!("redis error while draining clicks, retrying in {:?}: {:?}", delay, e);
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        if events.is_empty() {
+            backoff.reset();
+            continue;
+        }
+        match store.record_clicks(&events).await {
+            Ok(totals) => {
+                backoff.reset();
+                tracing::info!("stored {} clicks", events.len());
+                webhooks::emit_click_totals(store.as_ref(), &config.base_url, &totals).await;
+            }
+            Err(e) => {
+                let delay = backoff.next_delay();
+                tracing::error!("db error while storing clicks, retrying in {:?}: {:?}", delay, e);
+                if let Err(e) = requeue_clicks(&redis_pool, &events).await {
+                    tracing::error!("lost {} clicks, requeue failed: {:?}", events.len(), e);
+                }
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Drops links past their expiry from the redirect cache and tells their owners' webhooks, once
+/// per link. The links are not deleted: they keep answering and listing as expired.
+pub(crate) async fn sweep_expired(store:Arc<dyn LinkStore>, cache:Arc<RedirectCache>, base_url:String, every:Duration){
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        let links = match store.sweep_expired().await {
+            Ok(links) => links,
+            Err(e) => {
+                tracing::error!("db error while sweeping expired links: {:?}", e);
+                continue;
+            }
+        };
+        if links.is_empty() {
+            continue;
+        }
+        tracing::info!("swept {} expired links", links.len());
+        cache.evict_many(&links).await;
+        webhooks::emit(store.as_ref(), &base_url, webhooks::LINK_EXPIRED, &links).await;
+    }
+}
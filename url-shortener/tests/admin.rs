@@ -0,0 +1,42 @@
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use common::spawn_app;
+
+#[tokio::test]
+async fn create_stats_and_disable_from_the_cli() {
+    let app = spawn_app().await;
+
+    let short_url = app.cli(&["create", "https://example.com/from-cli", "--alias", "fromcli"]);
+    assert_eq!(short_url.trim(), format!("{}/fromcli", app.base_url));
+    let resp = app.get("/fromcli").await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()["location"], "https://example.com/from-cli");
+
+    let stats: Value = serde_json::from_str(&app.cli(&["stats", "fromcli", "--days", "7"])).unwrap();
+    assert_eq!(stats["original_url"], "https://example.com/from-cli");
+    assert_eq!(stats["days"], 7);
+
+    // a taken alias fails like it does over the api
+    let output = app.cli_output(&["create", "https://example.com/other", "--alias", "fromcli"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("alias not available"));
+
+    // disabling works whoever owns the link
+    let resp = app.shorten(serde_json::json!({"url": "https://example.com/owned", "custom_alias": "owned"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(app.cli(&["disable", "owned"]).trim(), "disabled owned");
+    assert_eq!(app.get("/owned").await.status(), StatusCode::NOT_FOUND);
+    assert!(!app.cli_output(&["disable", "owned"]).status.success());
+}
+
+#[tokio::test]
+async fn rejects_unknown_commands_and_options() {
+    let app = spawn_app().await;
+
+    assert!(!app.cli_output(&["frobnicate"]).status.success());
+    assert!(!app.cli_output(&["create", "https://example.com/", "--colour", "red"]).status.success());
+    assert!(!app.cli_output(&["stats"]).status.success());
+}
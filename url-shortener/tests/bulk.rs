@@ -0,0 +1,74 @@
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::{spawn_app, spawn_app_with, TestApp};
+
+async fn bulk(app:&TestApp, items:Vec<Value>)->reqwest::Response{
+    app.client.post(app.url("/api/shorten/bulk"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"items": items}))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn bulk_items_count_against_the_creation_limit() {
+    // hardly any refill while the test runs
+    let app = spawn_app_with(&[("RATE_LIMIT_BURST", "3"), ("RATE_LIMIT_PER_MINUTE", "1")]).await;
+
+    let items = (0..5).map(|i| json!({"url": format!("https://example.com/bulk/{}", i)})).collect();
+    let resp = bulk(&app, items).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await.unwrap();
+    let results = body["results"].as_array().unwrap();
+    let statuses: Vec<u64> = results.iter().map(|r| r["status"].as_u64().unwrap()).collect();
+    if app.limits_rates() {
+        assert_eq!(statuses, [201, 201, 201, 429, 429]);
+        assert_eq!(results[3]["error"], "rate_limited");
+        // the bulk request used up the same bucket as single creates
+        assert_eq!(app.shorten(json!({"url": "https://example.com/single"})).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    } else {
+        assert_eq!(statuses, [201; 5]);
+    }
+}
+
+#[tokio::test]
+async fn csv_round_trip_keeps_passwords_and_limits() {
+    let source = spawn_app().await;
+    for body in [
+        json!({"url": "https://example.com/locked", "custom_alias": "locked", "password": "hunter2", "max_clicks": 5}),
+        json!({"url": "https://example.com/pass", "custom_alias": "pass", "query_passthrough": true}),
+    ] {
+        assert_eq!(source.shorten(body).await.status(), StatusCode::CREATED);
+    }
+    assert_eq!(source.get("/pass").await.status(), StatusCode::FOUND);
+    let exported = source.cli(&["csv", "export"]);
+
+    let target = spawn_app().await;
+    let path = std::env::temp_dir().join(format!("shorty_export_{}.csv", std::process::id()));
+    std::fs::write(&path, exported).unwrap();
+    let report = target.cli(&["csv", "import", path.to_str().unwrap(), "--owner", "tester"]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(report.trim(), "imported 2, already present 0, rejected 0");
+
+    let resp = target.get("/locked").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!resp.text().await.unwrap().contains("example.com"));
+    let resp = target.client.post(target.url("/locked")).form(&[("password", "hunter2")]).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let page: Value = target.client.get(target.url("/api/links")).bearer_auth(&target.api_key).send().await.unwrap().json().await.unwrap();
+    let locked = page["links"].as_array().unwrap().iter().find(|l| l["code"] == "locked").unwrap();
+    assert_eq!(locked["max_clicks"], 5);
+    assert_eq!(target.get("/pass?ref=csv").await.headers()["location"], "https://example.com/pass?ref=csv");
+
+    // a password that cannot be carried over refuses the row instead of opening the link
+    let path = std::env::temp_dir().join(format!("shorty_bad_hash_{}.csv", std::process::id()));
+    std::fs::write(&path, "short_code,original_url,password_hash\nbadhash,https://example.com/bad,hunter2\n").unwrap();
+    let report = target.cli(&["csv", "import", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    // the warning for the row is logged on stdout too
+    assert!(report.trim().ends_with("imported 0, already present 0, rejected 1"), "{}", report);
+}
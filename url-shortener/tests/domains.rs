@@ -0,0 +1,131 @@
+mod common;
+
+use std::time::Duration;
+
+use reqwest::{header, StatusCode};
+use serde_json::{json, Value};
+
+use common::{spawn_app_with, TestApp};
+
+const DOMAIN: &str = "go.example.com";
+
+// the api picks up domains added through the cli on its next refresh
+async fn add_domain(app:&TestApp, host:&str){
+    assert_eq!(app.cli(&["domains", "add", host, "--owner", "tester"]).trim(), format!("added {} for tester", host));
+    for _ in 0..50 {
+        let list: Value = app.client.get(app.url("/api/domains")).bearer_auth(&app.api_key).send().await.unwrap().json().await.unwrap();
+        let known = list["domains"].as_array().unwrap().iter().any(|d| d["host"] == host);
+        // the list comes from the store, the map is reloaded right after
+        if known && app.shorten(json!({"url": "https://example.com/probe", "domain": host})).await.status().is_success() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("{} never became usable", host);
+}
+
+async fn get_on(app:&TestApp, host:&str, path:&str)->reqwest::Response{
+    app.client.get(app.url(path)).header(header::HOST, host).send().await.unwrap()
+}
+
+#[tokio::test]
+async fn codes_resolve_per_domain() {
+    let app = spawn_app_with(&[("DOMAIN_REFRESH_SECS", "1")]).await;
+    add_domain(&app, DOMAIN).await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/branded", "custom_alias": "promo", "domain": "Go.Example.com"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["short_url"], format!("http://{}/promo", DOMAIN));
+
+    // the same alias is still free on base_url
+    let resp = app.shorten(json!({"url": "https://example.com/plain", "custom_alias": "promo"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = app.shorten(json!({"url": "https://example.com/again", "custom_alias": "promo", "domain": DOMAIN})).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = get_on(&app, "go.example.com:8080", "/promo").await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()["location"], "https://example.com/branded");
+    let resp = app.get("/promo").await;
+    assert_eq!(resp.headers()["location"], "https://example.com/plain");
+    // unknown hosts are not served from base_url's codes
+    let resp = get_on(&app, "other.example.com", "/promo").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "unknown domain");
+
+    let resp = app.client.delete(app.url(&format!("/api/links/promo?domain={}", DOMAIN))).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(get_on(&app, DOMAIN, "/promo").await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(app.get("/promo").await.status(), StatusCode::FOUND);
+
+    let list: Value = app.client.get(app.url("/api/links?deleted=true")).bearer_auth(&app.api_key).send().await.unwrap().json().await.unwrap();
+    let branded = list["links"].as_array().unwrap().iter().find(|l| l["original_url"] == "https://example.com/branded").unwrap();
+    assert_eq!(branded["domain"], DOMAIN);
+}
+
+#[tokio::test]
+async fn domains_belong_to_one_owner() {
+    let app = spawn_app_with(&[("DOMAIN_REFRESH_SECS", "1")]).await;
+    add_domain(&app, DOMAIN).await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/", "domain": "nowhere.example.com"})).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // another owner can neither claim the host nor create links on it
+    assert!(!app.cli_output(&["domains", "add", DOMAIN, "--owner", "someone"]).status.success());
+    let other_key = app.cli(&["--create-api-key", "someone"]).trim().to_string();
+    let resp = app.client.post(app.url("/api/shorten"))
+        .bearer_auth(&other_key)
+        .json(&json!({"url": "https://example.com/", "domain": DOMAIN}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    assert!(!app.cli_output(&["domains", "add", "not a host", "--owner", "tester"]).status.success());
+    // add_domain left a link on it
+    assert!(!app.cli_output(&["domains", "remove", DOMAIN]).status.success());
+    assert!(app.cli(&["create", "https://example.com/cli", "--domain", DOMAIN, "--owner", "tester"]).starts_with("http://go.example.com/"));
+}
+
+#[tokio::test]
+async fn links_and_webhooks_cannot_point_at_our_domains() {
+    let app = spawn_app_with(&[("DOMAIN_REFRESH_SECS", "1")]).await;
+    add_domain(&app, DOMAIN).await;
+
+    let resp = app.shorten(json!({"url": "https://Go.Example.com./promo"})).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json::<Value>().await.unwrap()["rule"], "self_referential");
+
+    let resp = app.client.post(app.url("/api/webhooks"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"url": "https://go.example.com/hook", "events": ["link.created"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn fresh_domains_do_not_fall_back_to_base_url() {
+    // loaded once at startup, then not again during the test
+    let app = spawn_app_with(&[("DOMAIN_REFRESH_SECS", "3600")]).await;
+    assert_eq!(app.shorten(json!({"url": "https://example.com/base", "custom_alias": "promo"})).await.status(), StatusCode::CREATED);
+    let mut loaded = false;
+    for _ in 0..50 {
+        if get_on(&app, DOMAIN, "/promo").await.status() != StatusCode::SERVICE_UNAVAILABLE {
+            loaded = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(loaded, "custom domains never loaded");
+
+    // added after the load: its requests must not reach base_url's promo
+    assert_eq!(app.cli(&["domains", "add", DOMAIN, "--owner", "tester"]).trim(), format!("added {} for tester", DOMAIN));
+    let resp = get_on(&app, DOMAIN, "/promo").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "unknown domain");
+    assert_eq!(app.get("/promo").await.status(), StatusCode::FOUND);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn shorten_then_redirect() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/landing?ref=1"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = resp.json().await.unwrap();
+    let code = body["code"].as_str().unwrap();
+    assert_eq!(body["short_url"], format!("{}/{}", app.base_url, code));
+
+    let resp = app.get(&format!("/{}", code)).await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()["location"], "https://example.com/landing?ref=1");
+
+    // the same url again returns the existing link
+    let resp = app.shorten(json!({"url": "https://example.com/landing?ref=1"})).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.json::<Value>().await.unwrap()["code"], code);
+}
+
+#[tokio::test]
+async fn shorten_requires_api_key() {
+    let app = spawn_app().await;
+
+    let resp = app.client.post(app.url("/api/shorten"))
+        .json(&json!({"url": "https://example.com/"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rejects_unsafe_destinations() {
+    let app = spawn_app().await;
+
+    for (url, rule) in [("javascript:alert(1)", "scheme"), ("http://10.0.0.1/", "private_address"), ("not a url", "invalid")] {
+        let resp = app.shorten(json!({"url": url})).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", url);
+        assert_eq!(resp.json::<Value>().await.unwrap()["rule"], rule, "{}", url);
+    }
+}
+
+#[tokio::test]
+async fn expired_link_stops_redirecting() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/soon-gone", "ttl_seconds": 1})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let code = resp.json::<Value>().await.unwrap()["code"].as_str().unwrap().to_string();
+    assert_eq!(app.get(&format!("/{}", code)).await.status(), StatusCode::FOUND);
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let resp = app.get(&format!("/{}", code)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "expired");
+}
+
+#[tokio::test]
+async fn deleted_link_stops_redirecting() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/delete-me", "custom_alias": "deleteme"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    // warm the cache so the delete has something to evict
+    assert_eq!(app.get("/deleteme").await.status(), StatusCode::FOUND);
+
+    let resp = app.client.delete(app.url("/api/links/deleteme"))
+        .bearer_auth(&app.api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let resp = app.get("/deleteme").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "deleted");
+    // a second delete finds nothing, answered like the rest of the api
+    let resp = app.client.delete(app.url("/api/links/deleteme")).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.json::<Value>().await.unwrap()["error"], "not_found");
+    let resp = app.client.patch(app.url("/api/links/deleteme")).bearer_auth(&app.api_key).json(&json!({})).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json::<Value>().await.unwrap()["error"], "bad_request");
+}
+
+#[tokio::test]
+async fn hash_codes_do_not_run_out_for_one_url() {
+    let app = spawn_app_with(&[("CODE_STRATEGY", "hash")]).await;
+
+    // links with a ttl are never reused, so each one needs a code of its own
+    let mut codes = Vec::new();
+    for _ in 0..8 {
+        let resp = app.shorten(json!({"url": "https://example.com/popular", "ttl_seconds": 3600})).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        codes.push(resp.json::<Value>().await.unwrap()["code"].as_str().unwrap().to_string());
+    }
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), 8);
+}
+
+#[tokio::test]
+async fn generated_codes_skip_reserved_words() {
+    // the sequence starts at 62^3, which is "1000"
+    let app = spawn_app_with(&[("CODE_STRATEGY", "sequence"), ("RESERVED_ALIASES", "1000")]).await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/sequenced"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.json::<Value>().await.unwrap()["code"], "1001");
+}
+
+#[tokio::test]
+async fn info_and_stats_of_a_link() {
+    let mut app = spawn_app().await;
+    app.start_worker();
+
+    let resp = app.shorten(json!({"url": "https://example.com/counted", "custom_alias": "counted"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = app.client.get(app.url("/counted"))
+        .header("referer", "https://news.example.com/")
+        .header("user-agent", "stats-test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FOUND);
+
+    let resp = app.client.get(app.url("/api/info/counted")).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.json::<Value>().await.unwrap()["original_url"], "https://example.com/counted");
+
+    // clicks reach the clicks table in the background
+    let expected = if app.records_clicks() { 1 } else { 0 };
+    let mut stats = Value::Null;
+    for _ in 0..100 {
+        let resp = app.client.get(app.url("/api/stats/counted?days=7")).bearer_auth(&app.api_key).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        stats = resp.json().await.unwrap();
+        if stats["daily"].as_array().unwrap().len() == expected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(stats["days"], 7);
+    if app.records_clicks() {
+        assert_eq!(stats["clicks"], 1);
+        assert_eq!(stats["daily"][0]["clicks"], 1);
+        assert_eq!(stats["top_referers"], json!([{"value": "https://news.example.com/", "clicks": 1}]));
+        assert_eq!(stats["top_user_agents"], json!([{"value": "stats-test", "clicks": 1}]));
+    }
+
+    for path in ["/api/info/missing", "/api/stats/missing"] {
+        let resp = app.client.get(app.url(path)).bearer_auth(&app.api_key).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", path);
+        assert_eq!(resp.json::<Value>().await.unwrap()["error"], "not_found", "{}", path);
+    }
+}
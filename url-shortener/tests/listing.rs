@@ -0,0 +1,74 @@
+mod common;
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::{spawn_app, TestApp};
+
+async fn list(app:&TestApp, query:&str)->Value{
+    let resp = app.client.get(app.url(&format!("/api/links?{}", query)))
+        .bearer_auth(&app.api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK, "{}", query);
+    resp.json().await.unwrap()
+}
+
+fn codes(page:&Value)->Vec<&str>{
+    page["links"].as_array().unwrap().iter().map(|l| l["code"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn pages_newest_first() {
+    let app = spawn_app().await;
+
+    for i in 0..5 {
+        let resp = app.shorten(json!({"url": format!("https://example.com/page/{}", i), "custom_alias": format!("page{}", i)})).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+
+    let mut seen = Vec::new();
+    let mut query = "limit=2".to_string();
+    loop {
+        let page = list(&app, &query).await;
+        seen.extend(codes(&page).into_iter().map(str::to_string));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["page4", "page3", "page2", "page1", "page0"]);
+
+    let resp = app.client.get(app.url("/api/links?cursor=garbage"))
+        .bearer_auth(&app.api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn filters_and_search() {
+    let app = spawn_app().await;
+
+    for (alias, url) in [("docs", "https://docs.example.com/100%_done"), ("blog", "https://blog.example.com/"), ("gone", "https://gone.example.com/")] {
+        assert_eq!(app.shorten(json!({"url": url, "custom_alias": alias})).await.status(), StatusCode::CREATED);
+    }
+    let resp = app.shorten(json!({"url": "https://soon.example.com/", "custom_alias": "soon", "ttl_seconds": 1})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = app.client.delete(app.url("/api/links/gone")).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert_eq!(codes(&list(&app, "").await), ["soon", "blog", "docs"]);
+    assert_eq!(codes(&list(&app, "deleted=true").await), ["gone"]);
+    assert_eq!(codes(&list(&app, "expired=true").await), ["soon"]);
+    assert_eq!(codes(&list(&app, "expired=false").await), ["blog", "docs"]);
+    assert_eq!(codes(&list(&app, "q=BLOG").await), ["blog"]);
+    // wildcards in the search term are literal
+    assert_eq!(codes(&list(&app, "q=100%25_").await), ["docs"]);
+    assert_eq!(codes(&list(&app, "q=_").await), ["docs"]);
+}
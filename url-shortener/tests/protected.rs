@@ -0,0 +1,116 @@
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn password_gates_the_redirect() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/secret.pdf", "custom_alias": "secret", "password": "hunter2", "redirect_type": 301})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // neither the redirect nor the preview give the destination away
+    for path in ["/secret", "/secret+"] {
+        let resp = app.get(path).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", path);
+        let page = resp.text().await.unwrap();
+        assert!(page.contains(r#"action="/secret""#), "{}", path);
+        assert!(!page.contains("example.com"), "{}", path);
+    }
+
+    let resp = app.client.post(app.url("/secret")).form(&[("password", "wrong")]).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(resp.text().await.unwrap().contains("Wrong password"));
+
+    let resp = app.client.post(app.url("/secret")).form(&[("password", "hunter2")]).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()["location"], "https://example.com/secret.pdf");
+    assert_eq!(resp.headers()["cache-control"], "no-store");
+
+    // removing the password turns it back into a plain link, minus the permanent redirect caching
+    let resp = app.client.patch(app.url("/api/links/secret"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"password": null}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.json::<Value>().await.unwrap()["password_protected"], false);
+    assert_eq!(app.get("/secret").await.status(), StatusCode::MOVED_PERMANENTLY);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn click_limit_holds_under_concurrency() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/once", "custom_alias": "limited", "max_clicks": 3})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let requests: Vec<_> = (0..20)
+        .map(|_| tokio::spawn(app.client.get(app.url("/limited")).send()))
+        .collect();
+    let mut statuses = Vec::new();
+    for request in requests {
+        statuses.push(request.await.unwrap().unwrap().status());
+    }
+    assert_eq!(statuses.iter().filter(|s| **s == StatusCode::FOUND).count(), 3, "{:?}", statuses);
+    assert!(statuses.iter().all(|s| *s == StatusCode::FOUND || *s == StatusCode::NOT_FOUND), "{:?}", statuses);
+
+    let resp = app.get("/limited").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "expired");
+
+    // raising the limit brings the link back
+    let resp = app.client.patch(app.url("/api/links/limited"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"max_clicks": 4}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.json::<Value>().await.unwrap()["uses"], 3);
+    assert_eq!(app.get("/limited").await.status(), StatusCode::FOUND);
+    assert_eq!(app.get("/limited").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn forwarded_for_does_not_reset_the_guess_limit() {
+    let app = spawn_app_with(&[("RATE_LIMIT_BURST", "3"), ("RATE_LIMIT_PER_MINUTE", "1")]).await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/vault", "custom_alias": "vault", "password": "hunter2"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    // without trusted proxies the header is the client's word, the bucket stays on the peer address
+    let mut statuses = Vec::new();
+    for i in 0..4 {
+        let resp = app.client.post(app.url("/vault"))
+            .header("x-forwarded-for", format!("203.0.113.{}", i))
+            .form(&[("password", "wrong")])
+            .send()
+            .await
+            .unwrap();
+        statuses.push(resp.status());
+    }
+    if app.limits_rates() {
+        assert_eq!(statuses, [StatusCode::UNAUTHORIZED, StatusCode::UNAUTHORIZED, StatusCode::UNAUTHORIZED, StatusCode::TOO_MANY_REQUESTS]);
+    }
+}
+
+#[tokio::test]
+async fn guesses_are_capped_per_link() {
+    let app = spawn_app_with(&[("UNLOCK_PER_LINK_BURST", "2"), ("UNLOCK_PER_LINK_PER_MINUTE", "1")]).await;
+
+    for alias in ["first", "second"] {
+        let resp = app.shorten(json!({"url": "https://example.com/", "custom_alias": alias, "password": "hunter2"})).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+    let guess = |alias:&str, password:&str| app.client.post(app.url(&format!("/{}", alias))).form(&[("password", password)]).send();
+    assert_eq!(guess("first", "wrong").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(guess("first", "wrong").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    if app.limits_rates() {
+        // the per-address limit is far from reached, the link's own is not
+        assert_eq!(guess("first", "hunter2").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+    assert_eq!(guess("second", "hunter2").await.unwrap().status(), StatusCode::SEE_OTHER);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn routes_by_country_and_device() {
+    // the country header is only believed from a proxy
+    let app = spawn_app_with(&[("TRUSTED_PROXIES", "127.0.0.1")]).await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/default", "custom_alias": "split"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = app.client.put(app.url("/api/links/split/targets"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"targets": [
+            {"url": "https://example.com/everyone"},
+            {"url": "https://example.de/", "country": "de"},
+            {"url": "https://m.example.com/", "device": "mobile"},
+        ]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.json::<Value>().await.unwrap()["targets"][1]["country"], "DE");
+
+    let location = |resp: reqwest::Response| resp.headers()["location"].to_str().unwrap().to_string();
+    assert_eq!(location(app.get("/split").await), "https://example.com/everyone");
+    let resp = app.client.get(app.url("/split")).header("CF-IPCountry", "DE").send().await.unwrap();
+    assert_eq!(location(resp), "https://example.de/");
+    let resp = app.client.get(app.url("/split"))
+        .header("User-Agent", "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) Mobile/15E148")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(location(resp), "https://m.example.com/");
+
+    // an empty list turns the split off again
+    let resp = app.client.put(app.url("/api/links/split/targets"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"targets": []}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(location(app.get("/split").await), "https://example.com/default");
+}
+
+#[tokio::test]
+async fn rejects_invalid_targets() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/", "custom_alias": "badsplit"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    for target in [json!({"url": "http://10.0.0.1/"}), json!({"url": "https://example.com/", "country": "Germany"}), json!({"url": "https://example.com/", "device": "watch"})] {
+        let resp = app.client.put(app.url("/api/links/badsplit/targets"))
+            .bearer_auth(&app.api_key)
+            .json(&json!({"targets": [target]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", target);
+    }
+
+    let resp = app.client.put(app.url("/api/links/nosuchlink/targets"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"targets": []}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
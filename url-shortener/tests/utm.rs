@@ -0,0 +1,60 @@
+mod common;
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use common::spawn_app;
+
+#[tokio::test]
+async fn appends_utm_fields_on_create() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({
+        "url": "https://example.com/landing?ref=a%20b&utm_source=old#top",
+        "custom_alias": "campaign",
+        "utm_source": "newsletter",
+        "utm_medium": "email",
+        "utm_campaign": "spring sale",
+    })).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = app.get("/campaign").await;
+    assert_eq!(resp.headers()["location"], "https://example.com/landing?ref=a+b&utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale#top");
+
+    // without a conflicting parameter the original query is kept as it was
+    let resp = app.shorten(json!({"url": "https://example.com/?q=a%20b", "custom_alias": "plainutm", "utm_term": "shoes"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(app.get("/plainutm").await.headers()["location"], "https://example.com/?q=a%20b&utm_term=shoes");
+
+    let resp = app.shorten(json!({"url": "https://example.com/", "utm_source": " "})).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn passes_the_query_string_through_when_asked() {
+    let app = spawn_app().await;
+
+    let resp = app.shorten(json!({"url": "https://example.com/shop?utm_source=site", "custom_alias": "shop", "query_passthrough": true})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    // the destination's own parameters win, everything else is appended in order
+    let resp = app.get("/shop?utm_source=visitor&gclid=x1&tag=a&tag=b").await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()["location"], "https://example.com/shop?utm_source=site&gclid=x1&tag=a&tag=b");
+    assert_eq!(app.get("/shop").await.headers()["location"], "https://example.com/shop?utm_source=site");
+
+    // a plain link for the same url is not the passthrough one
+    let resp = app.shorten(json!({"url": "https://example.com/shop?utm_source=site"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let code = resp.json::<Value>().await.unwrap()["code"].as_str().unwrap().to_string();
+    assert_eq!(app.get(&format!("/{}?gclid=x1", code)).await.headers()["location"], "https://example.com/shop?utm_source=site");
+
+    // switched off again, the query string is ignored
+    let resp = app.client.patch(app.url("/api/links/shop"))
+        .bearer_auth(&app.api_key)
+        .json(&json!({"query_passthrough": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.json::<Value>().await.unwrap()["query_passthrough"], false);
+    assert_eq!(app.get("/shop?gclid=x1").await.headers()["location"], "https://example.com/shop?utm_source=site");
+}
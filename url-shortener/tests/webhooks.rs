@@ -0,0 +1,193 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, http::StatusCode as ReceiverStatus, routing::post, Router};
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use common::{spawn_app, spawn_app_with, TestApp};
+
+// the receivers listen on 127.0.0.1, which the webhook url policy refuses by default
+const ALLOW_PRIVATE: (&str, &str) = ("WEBHOOK_ALLOW_PRIVATE", "true");
+
+#[derive(Clone)]
+struct Receiver{
+    status: ReceiverStatus,
+    received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+}
+
+/// A local endpoint that records every delivery and answers with `status`.
+async fn start_receiver(status:ReceiverStatus)->(String, Receiver){
+    let receiver = Receiver{status, received: Arc::default()};
+    let app = Router::new()
+        .route("/hook", post(|State(r): State<Receiver>, headers: HeaderMap, body: Bytes| async move {
+            r.received.lock().unwrap().push((headers, body));
+            r.status
+        }))
+        .with_state(receiver.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, receiver)
+}
+
+async fn create_webhook(app:&TestApp, body:Value)->reqwest::Response{
+    app.client.post(app.url("/api/webhooks"))
+        .bearer_auth(&app.api_key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+// polls until `done` holds, deliveries are sent in the background
+async fn wait_for(what:&str, mut done:impl FnMut()->bool){
+    for _ in 0..150 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+#[tokio::test]
+async fn delivers_signed_link_events() {
+    let mut app = spawn_app_with(&[ALLOW_PRIVATE]).await;
+    app.start_worker();
+    let (hook_url, receiver) = start_receiver(ReceiverStatus::OK).await;
+
+    let resp = create_webhook(&app, json!({"url": hook_url, "events": ["link.created", "link.deleted", "link.clicks"], "click_thresholds": [1]})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let webhook: Value = resp.json().await.unwrap();
+    let secret = webhook["secret"].as_str().unwrap().to_string();
+    assert!(secret.starts_with("whsec_"));
+
+    let resp = app.shorten(json!({"url": "https://example.com/hooked", "custom_alias": "hooked"})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let mut expected = vec!["link.created", "link.deleted"];
+    if app.records_clicks() {
+        assert_eq!(app.get("/hooked").await.status(), StatusCode::FOUND);
+        expected.push("link.clicks");
+        wait_for("the click event", || receiver.received.lock().unwrap().len() == 2).await;
+    }
+    let resp = app.client.delete(app.url("/api/links/hooked")).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    wait_for("the deliveries", || receiver.received.lock().unwrap().len() == expected.len()).await;
+
+    let mut events = Vec::new();
+    for (headers, body) in receiver.received.lock().unwrap().iter() {
+        let timestamp = headers["x-shorty-timestamp"].to_str().unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(body);
+        let signature = headers["x-shorty-signature"].to_str().unwrap().strip_prefix("sha256=").unwrap();
+        let signature: Vec<u8> = (0..signature.len()).step_by(2).map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap()).collect();
+        mac.verify_slice(&signature).expect("signature does not match");
+
+        let payload: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload["type"], headers["x-shorty-event"].to_str().unwrap());
+        assert_eq!(payload["data"]["code"], "hooked");
+        assert_eq!(payload["data"]["original_url"], "https://example.com/hooked");
+        if payload["type"] == "link.clicks" {
+            assert_eq!(payload["data"]["threshold"], 1);
+        }
+        events.push(payload["type"].as_str().unwrap().to_string());
+    }
+    events.sort();
+    expected.sort();
+    assert_eq!(events, expected);
+}
+
+#[tokio::test]
+async fn swept_links_stay_expired_not_deleted() {
+    let mut app = spawn_app_with(&[ALLOW_PRIVATE, ("EXPIRY_SWEEP_SECS", "1")]).await;
+    app.start_worker();
+    let (hook_url, receiver) = start_receiver(ReceiverStatus::OK).await;
+    assert_eq!(create_webhook(&app, json!({"url": hook_url, "events": ["link.expired"]})).await.status(), StatusCode::CREATED);
+
+    let resp = app.shorten(json!({"url": "https://example.com/swept", "custom_alias": "swept", "ttl_seconds": 1})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    wait_for("the expiry event", || receiver.received.lock().unwrap().len() == 1).await;
+    let payload: Value = serde_json::from_slice(&receiver.received.lock().unwrap()[0].1).unwrap();
+    assert_eq!(payload["type"], "link.expired");
+    assert_eq!(payload["data"]["code"], "swept");
+
+    let resp = app.get("/swept").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().await.unwrap(), "expired");
+    for (query, listed) in [("expired=true", vec!["swept"]), ("", vec!["swept"]), ("deleted=true", vec![])] {
+        let page: Value = app.client.get(app.url(&format!("/api/links?{}", query))).bearer_auth(&app.api_key).send().await.unwrap().json().await.unwrap();
+        let codes: Vec<&str> = page["links"].as_array().unwrap().iter().map(|l| l["code"].as_str().unwrap()).collect();
+        assert_eq!(codes, listed, "{}", query);
+    }
+
+    // later sweeps leave it alone
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert_eq!(receiver.received.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn failing_deliveries_end_up_in_dead_letters() {
+    let mut app = spawn_app_with(&[ALLOW_PRIVATE, ("WEBHOOK_MAX_ATTEMPTS", "2"), ("WEBHOOK_RETRY_SECS", "1")]).await;
+    app.start_worker();
+    let (hook_url, receiver) = start_receiver(ReceiverStatus::INTERNAL_SERVER_ERROR).await;
+
+    let webhook: Value = create_webhook(&app, json!({"url": hook_url, "events": ["link.created"]})).await.json().await.unwrap();
+    assert_eq!(app.shorten(json!({"url": "https://example.com/undeliverable"})).await.status(), StatusCode::CREATED);
+    wait_for("the retry", || receiver.received.lock().unwrap().len() == 2).await;
+
+    let mut dead_letters = Value::Null;
+    for _ in 0..50 {
+        let resp = app.client.get(app.url(&format!("/api/webhooks/dead-letters?webhook_id={}", webhook["id"])))
+            .bearer_auth(&app.api_key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        dead_letters = resp.json::<Value>().await.unwrap()["dead_letters"].clone();
+        if !dead_letters.as_array().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let dead = &dead_letters[0];
+    assert_eq!(dead["event"], "link.created");
+    assert_eq!(dead["attempts"], 2);
+    assert!(dead["last_error"].as_str().unwrap().contains("500"));
+    assert_eq!(dead["payload"]["data"]["original_url"], "https://example.com/undeliverable");
+}
+
+#[tokio::test]
+async fn manages_webhook_subscriptions() {
+    let app = spawn_app().await;
+
+    // private addresses are refused unless the deployment allows them, also embedded in nat64 and 6to4 ones
+    for url in ["http://127.0.0.1:9/hook", "http://[64:ff9b::a9fe:a9fe]/hook", "http://[2002:a00:1::]/hook"] {
+        let resp = create_webhook(&app, json!({"url": url, "events": ["link.created"]})).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", url);
+    }
+    let resp = create_webhook(&app, json!({"url": "https://hooks.example.com/", "events": ["link.renamed"]})).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = create_webhook(&app, json!({"url": "https://hooks.example.com/", "events": ["link.clicks"]})).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = create_webhook(&app, json!({"url": "https://hooks.example.com/", "events": ["link.expired", "link.expired"]})).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = resp.json().await.unwrap();
+    assert_eq!(created["events"], json!(["link.expired"]));
+
+    let list: Value = app.client.get(app.url("/api/webhooks")).bearer_auth(&app.api_key).send().await.unwrap().json().await.unwrap();
+    assert_eq!(list["webhooks"].as_array().unwrap().len(), 1);
+    assert!(list["webhooks"][0].get("secret").is_none());
+
+    let path = format!("/api/webhooks/{}", created["id"]);
+    let resp = app.client.delete(app.url(&path)).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = app.client.delete(app.url(&path)).bearer_auth(&app.api_key).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}